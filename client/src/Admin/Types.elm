@@ -4,6 +4,7 @@ import Capsule exposing (Capsule)
 import Json.Decode as Decode exposing (Decoder)
 import Status exposing (Status)
 import User as UserMod
+import Utils exposing (andMap)
 
 
 type alias User =
@@ -45,6 +46,99 @@ decodeDashboard =
         (Decode.field "capsules" (Decode.list Capsule.decode))
 
 
+type alias HistogramEntry =
+    { period : Int
+    , count : Int
+    }
+
+
+decodeHistogram : Decoder (List HistogramEntry)
+decodeHistogram =
+    Decode.list
+        (Decode.map2 HistogramEntry
+            (Decode.field "period" Decode.int)
+            (Decode.field "count" Decode.int)
+        )
+
+
+type alias UserDiskUsage =
+    { username : String
+    , diskUsage : Int
+    , diskQuota : Int
+    }
+
+
+decodeUserDiskUsage : Decoder UserDiskUsage
+decodeUserDiskUsage =
+    Decode.map3 UserDiskUsage
+        (Decode.field "username" Decode.string)
+        (Decode.field "disk_usage" Decode.int)
+        (Decode.field "disk_quota" Decode.int)
+
+
+type alias RunningTasks =
+    { videoUpload : Int
+    , production : Int
+    , publication : Int
+    }
+
+
+decodeRunningTasks : Decoder RunningTasks
+decodeRunningTasks =
+    Decode.map3 RunningTasks
+        (Decode.field "video_upload" Decode.int)
+        (Decode.field "production" Decode.int)
+        (Decode.field "publication" Decode.int)
+
+
+type alias Queue =
+    { slots : Int
+    , active : Int
+    , waiting : Int
+    }
+
+
+decodeQueue : Decoder Queue
+decodeQueue =
+    Decode.map3 Queue
+        (Decode.field "slots" Decode.int)
+        (Decode.field "active" Decode.int)
+        (Decode.field "waiting" Decode.int)
+
+
+type alias Stats =
+    { period : String
+    , usersPerPlan : List ( String, Int )
+    , activations : List HistogramEntry
+    , capsulesCreated : List HistogramEntry
+    , capsulesProduced : List HistogramEntry
+    , capsulesPublished : List HistogramEntry
+    , capsules : Int
+    , diskUsage : Int
+    , diskUsagePerUser : List UserDiskUsage
+    , videoMinutes : Float
+    , runningTasks : RunningTasks
+    , queue : Queue
+    }
+
+
+decodeStats : Decoder Stats
+decodeStats =
+    Decode.succeed Stats
+        |> andMap (Decode.field "period" Decode.string)
+        |> andMap (Decode.field "users_per_plan" (Decode.keyValuePairs Decode.int))
+        |> andMap (Decode.field "activations" decodeHistogram)
+        |> andMap (Decode.field "capsules_created" decodeHistogram)
+        |> andMap (Decode.field "capsules_produced" decodeHistogram)
+        |> andMap (Decode.field "capsules_published" decodeHistogram)
+        |> andMap (Decode.field "capsules" Decode.int)
+        |> andMap (Decode.field "disk_usage" Decode.int)
+        |> andMap (Decode.field "disk_usage_per_user" (Decode.list decodeUserDiskUsage))
+        |> andMap (Decode.field "video_minutes" Decode.float)
+        |> andMap (Decode.field "running_tasks" decodeRunningTasks)
+        |> andMap (Decode.field "queue" decodeQueue)
+
+
 type Page
     = Dashboard
     | UsersPage Int
@@ -56,7 +150,7 @@ type alias Model =
     { page : Page
     , users : List User
     , capsules : List Capsule
-    , stats : String
+    , stats : Maybe Stats
     , usernameSearch : Maybe String
     , emailSearch : Maybe String
     , usernameSearchStatus : Status
@@ -74,7 +168,7 @@ initModel page =
     { page = page
     , users = []
     , capsules = []
-    , stats = "dummy stats"
+    , stats = Nothing
     , usernameSearch = Nothing
     , emailSearch = Nothing
     , usernameSearchStatus = Status.NotSent
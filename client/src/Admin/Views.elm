@@ -29,7 +29,7 @@ view global model =
         ( content, popup ) =
             case page of
                 Admin.Dashboard ->
-                    ( dashboardView global model.users model.capsules, Nothing )
+                    ( dashboardView global model.stats, Nothing )
 
                 Admin.UsersPage p ->
                     ( usersView global model p, Nothing )
@@ -43,14 +43,107 @@ view global model =
     ( Element.el [ Ui.wf, Ui.hf, Element.padding 10 ] content, popup )
 
 
-dashboardView : Core.Global -> List Admin.User -> List Capsule -> Element Core.Msg
-dashboardView _ _ _ =
-    Element.column [ Element.spacing 30, Ui.wf, Ui.hf ]
-        [ Element.el [ Font.bold, Font.size 24, Element.centerX, Element.padding 50 ] <| Element.text "Ici on affichera des stats"
+dashboardView : Core.Global -> Maybe Admin.Stats -> Element Core.Msg
+dashboardView global stats =
+    let
+        content =
+            case stats of
+                Just s ->
+                    statsView global s
+
+                Nothing ->
+                    Element.el [ Element.centerX ] Ui.spinner
+    in
+    Element.column [ Element.spacing 30, Ui.wf, Ui.hf, Element.padding 50 ]
+        [ content
         , Element.el [ Element.centerX ] (Ui.primaryButton { onPress = Just (Core.AdminMsg Admin.ClearWebockets), label = Element.text "Clear websockets" })
         ]
 
 
+statsView : Core.Global -> Admin.Stats -> Element Core.Msg
+statsView global stats =
+    let
+        title t =
+            Element.el [ Font.bold, Font.size 20 ] (Element.text t)
+
+        line ( label, value ) =
+            Element.row [ Element.spacing 10 ]
+                [ Element.el [ Font.bold ] (Element.text (label ++ " :"))
+                , Element.text value
+                ]
+
+        section t lines =
+            Element.column [ Element.spacing 10, Element.alignTop ] (title t :: List.map line lines)
+
+        totals =
+            section "Totals"
+                [ ( "Capsules", String.fromInt stats.capsules )
+                , ( "Disk usage", String.fromInt stats.diskUsage ++ " Mo" )
+                , ( "Produced video", String.fromInt (round stats.videoMinutes) ++ " min" )
+                ]
+
+        plans =
+            section "Users per plan" (List.map (\( plan, count ) -> ( plan, String.fromInt count )) stats.usersPerPlan)
+
+        tasks =
+            section "Tasks"
+                [ ( "Video uploads", String.fromInt stats.runningTasks.videoUpload )
+                , ( "Productions", String.fromInt stats.runningTasks.production )
+                , ( "Publications", String.fromInt stats.runningTasks.publication )
+                , ( "Active slots", String.fromInt stats.queue.active ++ " / " ++ String.fromInt stats.queue.slots )
+                , ( "Waiting", String.fromInt stats.queue.waiting )
+                ]
+
+        diskUsagePerUser =
+            section "Disk usage per user"
+                (List.map
+                    (\x -> ( x.username, String.fromInt x.diskUsage ++ " Mo / " ++ String.fromInt x.diskQuota ++ " Go" ))
+                    stats.diskUsagePerUser
+                )
+
+        histograms =
+            Element.wrappedRow [ Element.spacing 50 ]
+                [ histogramView global "Activations" stats.activations
+                , histogramView global "Created capsules" stats.capsulesCreated
+                , histogramView global "Produced capsules" stats.capsulesProduced
+                , histogramView global "Published capsules" stats.capsulesPublished
+                ]
+    in
+    Element.column [ Element.spacing 50, Element.centerX ]
+        [ Element.wrappedRow [ Element.spacing 50 ] [ totals, plans, tasks, diskUsagePerUser ]
+        , title ("Per " ++ stats.period)
+        , histograms
+        ]
+
+
+histogramView : Core.Global -> String -> List Admin.HistogramEntry -> Element Core.Msg
+histogramView global title entries =
+    let
+        highest =
+            entries |> List.map .count |> List.maximum |> Maybe.withDefault 0
+
+        bar entry =
+            Element.row [ Element.spacing 10 ]
+                [ Element.el [ Element.width (Element.px 150) ] (Element.text (TimeUtils.dateToString global.lang global.zone entry.period))
+                , Element.el
+                    [ Element.width (Element.px (200 * entry.count // max 1 highest))
+                    , Element.height (Element.px 15)
+                    , Background.color Colors.navbarOver
+                    ]
+                    Element.none
+                , Element.text (String.fromInt entry.count)
+                ]
+
+        bars =
+            if List.isEmpty entries then
+                [ Element.text "-" ]
+
+            else
+                List.map bar entries
+    in
+    Element.column [ Element.spacing 5, Element.alignTop ] (Element.el [ Font.bold ] (Element.text title) :: bars)
+
+
 shortUsersView : Core.Global -> List Capsule.User -> Element Core.Msg
 shortUsersView global users =
     let
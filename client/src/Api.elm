@@ -198,9 +198,9 @@ updateCapsule resultToMsg capsule =
 
 
 deleteProject : Core.Msg -> String -> Cmd Core.Msg
-deleteProject resultToMsg project =
+deleteProject resultToMsg id =
     delete
-        { url = "/api/project/" ++ project
+        { url = "/api/project/" ++ id
         , expect = Http.expectWhatever (ignoreError (\_ -> resultToMsg))
         , body = Http.emptyBody
         }
@@ -456,11 +456,11 @@ ignoreError convert value =
             convert o
 
 
-dashboard : (Result Http.Error String -> msg) -> Cmd msg
+dashboard : (Result Http.Error Admin.Stats -> msg) -> Cmd msg
 dashboard resultToMsg =
     get
         { url = "/api/admin/dashboard"
-        , expect = Http.expectJson resultToMsg (Decode.field "stats" Decode.string)
+        , expect = Http.expectJson resultToMsg Admin.decodeStats
         , body = Http.emptyBody
         }
 
@@ -9,6 +9,7 @@ import Utils exposing (andMap, tern)
 type Role
     = Owner
     | Write
+    | Comment
     | Read
 
 
@@ -21,6 +22,9 @@ encodeRole role =
         Write ->
             "write"
 
+        Comment ->
+            "comment"
+
         Read ->
             "read"
 
@@ -34,6 +38,9 @@ decodeRoleString role =
         "write" ->
             Just Write
 
+        "comment" ->
+            Just Comment
+
         "read" ->
             Just Read
 
@@ -168,15 +175,19 @@ type alias Slide =
     { uuid : String
     , extra : Maybe String
     , prompt : String
+    , durationMs : Maybe Int
+    , transition : Transition
     }
 
 
 decodeSlide : Decoder Slide
 decodeSlide =
-    Decode.map3 Slide
+    Decode.map5 Slide
         (Decode.field "uuid" Decode.string)
         (Decode.maybe (Decode.field "extra" Decode.string))
         (Decode.field "prompt" Decode.string)
+        (Decode.maybe (Decode.field "duration_ms" Decode.int))
+        decodeTransitionField
 
 
 encodeSlide : Slide -> Encode.Value
@@ -192,9 +203,21 @@ encodeSlide slide =
                     Encode.null
           )
         , ( "prompt", Encode.string slide.prompt )
+        , ( "duration_ms", encodeMaybeInt slide.durationMs )
+        , ( "transition", encodeTransition slide.transition )
         ]
 
 
+encodeMaybeInt : Maybe Int -> Encode.Value
+encodeMaybeInt value =
+    case value of
+        Just i ->
+            Encode.int i
+
+        _ ->
+            Encode.null
+
+
 slidePath : Capsule -> Slide -> String
 slidePath capsule slide =
     assetPath capsule (slide.uuid ++ ".png")
@@ -355,6 +378,7 @@ type alias Gos =
     , events : List Event
     , webcamSettings : WebcamSettings
     , fade : Fade
+    , transition : Transition
     }
 
 
@@ -542,14 +566,116 @@ encodeFade f =
         ]
 
 
+type TransitionType
+    = Cut
+    | Crossfade
+    | FadeBlack
+    | SlideLeft
+    | SlideRight
+    | WipeLeft
+    | WipeRight
+
+
+decodeTransitionType : Decoder TransitionType
+decodeTransitionType =
+    Decode.string
+        |> Decode.andThen
+            (\str ->
+                case str of
+                    "cut" ->
+                        Decode.succeed Cut
+
+                    "crossfade" ->
+                        Decode.succeed Crossfade
+
+                    "fade_black" ->
+                        Decode.succeed FadeBlack
+
+                    "slide_left" ->
+                        Decode.succeed SlideLeft
+
+                    "slide_right" ->
+                        Decode.succeed SlideRight
+
+                    "wipe_left" ->
+                        Decode.succeed WipeLeft
+
+                    "wipe_right" ->
+                        Decode.succeed WipeRight
+
+                    x ->
+                        Decode.fail <| "Unknown transition: " ++ x
+            )
+
+
+encodeTransitionType : TransitionType -> Encode.Value
+encodeTransitionType ty =
+    Encode.string
+        (case ty of
+            Cut ->
+                "cut"
+
+            Crossfade ->
+                "crossfade"
+
+            FadeBlack ->
+                "fade_black"
+
+            SlideLeft ->
+                "slide_left"
+
+            SlideRight ->
+                "slide_right"
+
+            WipeLeft ->
+                "wipe_left"
+
+            WipeRight ->
+                "wipe_right"
+        )
+
+
+type alias Transition =
+    { ty : TransitionType
+    , durationMs : Int
+    }
+
+
+defaultTransition : Transition
+defaultTransition =
+    { ty = Cut, durationMs = 0 }
+
+
+decodeTransition : Decoder Transition
+decodeTransition =
+    Decode.map2 Transition
+        (Decode.field "ty" decodeTransitionType)
+        (Decode.field "duration_ms" Decode.int)
+
+
+decodeTransitionField : Decoder Transition
+decodeTransitionField =
+    Decode.maybe (Decode.field "transition" decodeTransition)
+        |> Decode.map (Maybe.withDefault defaultTransition)
+
+
+encodeTransition : Transition -> Encode.Value
+encodeTransition t =
+    Encode.object
+        [ ( "ty", encodeTransitionType t.ty )
+        , ( "duration_ms", Encode.int t.durationMs )
+        ]
+
+
 decodeGos : Decoder Gos
 decodeGos =
-    Decode.map5 Gos
+    Decode.map6 Gos
         (Decode.maybe (Decode.field "record" decodeRecord))
         (Decode.field "slides" (Decode.list decodeSlide))
         (Decode.field "events" (Decode.list decodeEvent))
         (Decode.field "webcam_settings" decodeWebcamSettings)
         (Decode.field "fade" decodeFade)
+        decodeTransitionField
 
 
 type alias User =
@@ -578,6 +704,8 @@ type alias Capsule =
     , promptSubtitles : Bool
     , diskUsage : Int
     , durationMs : Int
+    , slideDurationMs : Maybe Int
+    , projectId : Maybe String
     }
 
 
@@ -598,6 +726,8 @@ decode =
         |> andMap (Decode.field "prompt_subtitles" Decode.bool)
         |> andMap (Decode.field "disk_usage" Decode.int)
         |> andMap (Decode.field "duration_ms" Decode.int)
+        |> andMap (Decode.maybe (Decode.field "slide_duration_ms" Decode.int))
+        |> andMap (Decode.maybe (Decode.field "project_id" Decode.string))
 
 
 encodeGos : Gos -> Encode.Value
@@ -608,6 +738,7 @@ encodeGos gos =
         , ( "events", Encode.list encodeEvent gos.events )
         , ( "webcam_settings", encodeWebcamSettings gos.webcamSettings )
         , ( "fade", encodeFade gos.fade )
+        , ( "transition", encodeTransition gos.transition )
         ]
 
 
@@ -624,6 +755,7 @@ encode capsule =
         , ( "name", Encode.string capsule.name )
         , ( "privacy", encodePrivacy capsule.privacy )
         , ( "prompt_subtitles", Encode.bool capsule.promptSubtitles )
+        , ( "slide_duration_ms", encodeMaybeInt capsule.slideDurationMs )
         , ( "structure", encodeStructure capsule.structure )
         ]
 
@@ -636,6 +768,7 @@ encodeAll capsule =
         , ( "name", Encode.string capsule.name )
         , ( "privacy", encodePrivacy capsule.privacy )
         , ( "prompt_subtitles", Encode.bool capsule.promptSubtitles )
+        , ( "slide_duration_ms", encodeMaybeInt capsule.slideDurationMs )
         , ( "structure", encodeStructure capsule.structure )
         , ( "produced", Encode.bool (capsule.produced == Done) )
         ]
@@ -79,7 +79,7 @@ viewRole global model u =
             let
                 options : List Capsule.Role
                 options =
-                    [ Capsule.Read, Capsule.Write ]
+                    [ Capsule.Read, Capsule.Comment, Capsule.Write ]
 
                 optionToHtml : Capsule.Role -> Html.Html Core.Msg
                 optionToHtml option =
@@ -115,7 +115,7 @@ viewRole global model u =
                 let
                     options : List Capsule.Role
                     options =
-                        [ Capsule.Read, Capsule.Write ]
+                        [ Capsule.Read, Capsule.Comment, Capsule.Write ]
 
                     optionToHtml : Capsule.Role -> Html.Html Core.Msg
                     optionToHtml option =
@@ -427,7 +427,9 @@ actionsView global projectOrCapsule =
                     Core.SlideUploadRequested (Just project.name) |> Just
 
                 deleteProjectMsg =
-                    Core.RequestDeleteProject project.name |> Just
+                    List.head project.capsules
+                        |> Maybe.andThen .projectId
+                        |> Maybe.map Core.RequestDeleteProject
             in
             Element.row [ Element.spacing 10, Element.centerY ]
                 [ -- iconButton Nothing Fa.pen Nothing (Lang.renameProject global.lang)
@@ -269,7 +269,7 @@ type Msg
     | ExportCapsule Capsule
     | CapsuleChanged Capsule
     | ExtraCapsuleReceived Route Capsule
-    | AdminDashboard String
+    | AdminDashboard Admin.Stats
     | AdminUsers (List Admin.User) Int
     | AdminUser Admin.User
     | AdminCapsules (List Capsule) Int
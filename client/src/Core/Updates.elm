@@ -116,44 +116,43 @@ updateModel msg model =
                     , Ports.select
                         ( project
                         , if User.isPremium user then
-                            [ "application/pdf", "application/zip" ]
+                            "application/zip" :: slidesMimes
 
                           else
-                            [ "application/pdf" ]
+                            slidesMimes
                         )
                     )
 
                 -- Select.file [ "application/pdf", "application/zip" ] (Core.SlideUploaded project) )
                 Core.SlideUploaded project file ->
-                    case file.mime of
-                        "application/pdf" ->
-                            let
-                                name =
-                                    file.name
-                                        |> String.split "."
-                                        |> List.reverse
-                                        |> List.drop 1
-                                        |> List.reverse
-                                        |> String.join "."
-
-                                newCapsule =
-                                    NewCapsule.init project name
-                            in
-                            ( { model | page = Core.NewCapsule newCapsule }
-                            , Api.uploadSlideShow newCapsule.project file
-                            )
-
-                        "application/zip" ->
-                            ( model
-                            , if User.isPremium user then
-                                Ports.importCapsule ( project, file.value )
-
-                              else
-                                Cmd.none
-                            )
-
-                        _ ->
-                            ( model, Cmd.none )
+                    if List.member file.mime slidesMimes then
+                        let
+                            name =
+                                file.name
+                                    |> String.split "."
+                                    |> List.reverse
+                                    |> List.drop 1
+                                    |> List.reverse
+                                    |> String.join "."
+
+                            newCapsule =
+                                NewCapsule.init project name
+                        in
+                        ( { model | page = Core.NewCapsule newCapsule }
+                        , Api.uploadSlideShow newCapsule.project file
+                        )
+
+                    else if file.mime == "application/zip" then
+                        ( model
+                        , if User.isPremium user then
+                            Ports.importCapsule ( project, file.value )
+
+                          else
+                            Cmd.none
+                        )
+
+                    else
+                        ( model, Cmd.none )
 
                 Core.SlideUploadResponded response ->
                     let
@@ -207,14 +206,14 @@ updateModel msg model =
                 Core.Cancel ->
                     ( { model | popup = Nothing }, Cmd.none )
 
-                Core.RequestDeleteProject name ->
+                Core.RequestDeleteProject id ->
                     let
                         newPopup =
                             Popup.popup
                                 (Lang.warning global.lang)
                                 (Lang.deleteProjectConfirm global.lang)
                                 Core.Cancel
-                                (Core.DeleteProject name)
+                                (Core.DeleteProject id)
                     in
                     ( { model | popup = Just newPopup }, Cmd.none )
 
@@ -234,9 +233,9 @@ updateModel msg model =
                     , Api.deleteCapsule (\_ -> Core.Noop) id
                     )
 
-                Core.DeleteProject name ->
-                    ( { model | user = User.removeProject name user, popup = Nothing }
-                    , Api.deleteProject Core.Noop name
+                Core.DeleteProject id ->
+                    ( { model | user = User.removeProject id user, popup = Nothing }
+                    , Api.deleteProject Core.Noop id
                     )
 
                 Core.OnUrlChange url ->
@@ -395,7 +394,7 @@ updateModel msg model =
                         adminModel =
                             Admin.initModel Admin.Dashboard
                     in
-                    ( { model | page = Core.Admin { adminModel | stats = dashboard } }, Cmd.none )
+                    ( { model | page = Core.Admin { adminModel | stats = Just dashboard } }, Cmd.none )
 
                 Core.AdminUsers users pagination ->
                     let
@@ -439,3 +438,12 @@ updateModel msg model =
 
         _ ->
             ( newModel, Cmd.batch [ newCmd, unbindWebcam, showWarningCmd ] )
+
+
+slidesMimes : List String
+slidesMimes =
+    [ "application/pdf"
+    , "application/vnd.openxmlformats-officedocument.presentationml.presentation"
+    , "application/vnd.ms-powerpoint"
+    , "application/vnd.oasis.opendocument.presentation"
+    ]
@@ -170,7 +170,7 @@ pageFromRoute global user currentPage route extraCapsule =
 
         Route.Admin Route.Dashboard ->
             let
-                resultToMsg2 : Result Http.Error String -> Core.Msg
+                resultToMsg2 : Result Http.Error Admin.Stats -> Core.Msg
                 resultToMsg2 result =
                     case result of
                         Ok dashboard ->
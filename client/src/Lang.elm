@@ -251,6 +251,9 @@ roleView lang r =
         ( FrFr, Capsule.Write ) ->
             "Écriture"
 
+        ( FrFr, Capsule.Comment ) ->
+            "Relecture"
+
         ( FrFr, Capsule.Read ) ->
             "Lecture"
 
@@ -260,6 +263,9 @@ roleView lang r =
         ( _, Capsule.Write ) ->
             "Write"
 
+        ( _, Capsule.Comment ) ->
+            "Review"
+
         ( _, Capsule.Read ) ->
             "Read"
 
@@ -60,6 +60,7 @@ gosFromSlide slide =
     , events = []
     , webcamSettings = Capsule.defaultWebcamSettings
     , fade = { vfadein = Nothing, vfadeout = Nothing, afadein = Nothing, afadeout = Nothing }
+    , transition = Capsule.defaultTransition
     }
 
 
@@ -419,6 +419,7 @@ extractStructureAux slides current currentGos =
                             , events = []
                             , webcamSettings = Capsule.defaultWebcamSettings
                             , fade = { afadein = Nothing, afadeout = Nothing, vfadein = Nothing, vfadeout = Nothing }
+                            , transition = Capsule.defaultTransition
                             }
 
                         ( Preparation.Slide _ s, Just gos ) ->
@@ -434,6 +435,7 @@ extractStructureAux slides current currentGos =
                             , events = []
                             , webcamSettings = Capsule.defaultWebcamSettings
                             , fade = { afadein = Nothing, afadeout = Nothing, vfadein = Nothing, vfadeout = Nothing }
+                            , transition = Capsule.defaultTransition
                             }
             in
             extractStructureAux t newCurrent (Just newGos)
@@ -1,4 +1,4 @@
-module TimeUtils exposing (timeToString)
+module TimeUtils exposing (dateToString, timeToString)
 
 import Lang exposing (Lang)
 import Time
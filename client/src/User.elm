@@ -221,10 +221,10 @@ removeCapsule id user =
 
 
 removeProjectIter : String -> Project -> Maybe Project
-removeProjectIter name project =
+removeProjectIter id project =
     let
         capsules =
-            List.filter (\x -> x.project /= name || x.role /= Capsule.Owner) project.capsules
+            List.filter (\x -> x.projectId /= Just id || x.role /= Capsule.Owner) project.capsules
     in
     case capsules of
         [] ->
@@ -235,8 +235,8 @@ removeProjectIter name project =
 
 
 removeProject : String -> User -> User
-removeProject name user =
-    { user | projects = List.filterMap (removeProjectIter name) user.projects }
+removeProject id user =
+    { user | projects = List.filterMap (removeProjectIter id) user.projects }
 
 
 addCapsule : Capsule -> User -> User
@@ -0,0 +1,5 @@
+UPDATE capsules_users_join SET role = 'read' WHERE role = 'comment';
+ALTER TYPE role RENAME TO role_old;
+CREATE TYPE role AS ENUM ('read', 'write', 'owner');
+ALTER TABLE capsules_users_join ALTER COLUMN role TYPE role USING role::text::role;
+DROP TYPE role_old;
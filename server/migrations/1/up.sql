@@ -0,0 +1 @@
+ALTER TYPE role ADD VALUE 'comment' BEFORE 'write';
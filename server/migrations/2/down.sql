@@ -0,0 +1,12 @@
+BEGIN;
+
+ALTER TABLE capsules ADD COLUMN project_name VARCHAR NOT NULL DEFAULT '';
+UPDATE capsules c SET project_name = p.name FROM projects p WHERE p.id = c.project;
+ALTER TABLE capsules DROP COLUMN project;
+ALTER TABLE capsules RENAME COLUMN project_name TO project;
+ALTER TABLE capsules ALTER COLUMN project DROP DEFAULT;
+
+DROP TABLE project_shares;
+DROP TABLE projects;
+
+COMMIT;
@@ -0,0 +1,58 @@
+-- The project names of the capsules are replaced by the id of a project of their owner, the
+-- projects keeping the order of the most recent capsules first. Every step can be run again.
+BEGIN;
+
+CREATE TABLE IF NOT EXISTS projects (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    description VARCHAR NOT NULL,
+    capsule_order JSON NOT NULL,
+    owner INT NOT NULL REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS project_shares (
+    id SERIAL PRIMARY KEY,
+    role role NOT NULL,
+    granted JSON NOT NULL,
+    project INT NOT NULL REFERENCES projects (id) ON DELETE CASCADE,
+    member INT NOT NULL REFERENCES users (id) ON DELETE CASCADE
+);
+
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_name = 'capsules' AND column_name = 'project'
+            AND data_type = 'character varying'
+    ) THEN
+        ALTER TABLE capsules RENAME COLUMN project TO project_name;
+        ALTER TABLE capsules ADD COLUMN project INT;
+
+        INSERT INTO projects (name, description, capsule_order, owner)
+            SELECT n.project_name, '', '[]'::json, n.users_id
+            FROM (
+                SELECT DISTINCT c.project_name, j.users_id
+                FROM capsules c
+                JOIN capsules_users_join j ON j.capsules_id = c.id AND j.role = 'owner'
+                WHERE c.project_name <> ''
+            ) n
+            WHERE NOT EXISTS (
+                SELECT 1 FROM projects p WHERE p.name = n.project_name AND p.owner = n.users_id
+            );
+
+        UPDATE capsules c SET project = p.id
+            FROM capsules_users_join j, projects p
+            WHERE j.capsules_id = c.id AND j.role = 'owner'
+                AND p.owner = j.users_id AND p.name = c.project_name;
+
+        UPDATE projects p SET capsule_order = COALESCE(
+                (SELECT json_agg(c.id ORDER BY c.last_modified DESC)
+                FROM capsules c WHERE c.project = p.id),
+                '[]'::json
+            );
+
+        ALTER TABLE capsules DROP COLUMN project_name;
+    END IF;
+END $$;
+
+COMMIT;
@@ -0,0 +1,49 @@
+BEGIN;
+
+DROP TABLE ownership_transfers;
+DROP TABLE pending_invitations;
+DROP TABLE data_exports;
+DROP TABLE folders_capsules_join;
+DROP TABLE folders;
+DROP TABLE tags_capsules_join;
+DROP TABLE tags;
+DROP TABLE comments;
+DROP TABLE audit_events;
+
+ALTER TABLE capsules DROP COLUMN audio_tracks;
+ALTER TABLE capsules DROP COLUMN slide_duration_ms;
+ALTER TABLE capsules DROP COLUMN output_settings;
+ALTER TABLE capsules DROP COLUMN published_on;
+ALTER TABLE capsules DROP COLUMN produced_on;
+ALTER TABLE capsules DROP COLUMN created;
+ALTER TABLE capsules DROP COLUMN deleted;
+ALTER TABLE capsules DROP COLUMN approval_comment;
+ALTER TABLE capsules DROP COLUMN approval_required;
+ALTER TABLE capsules DROP COLUMN approval;
+ALTER TABLE capsules DROP COLUMN organization;
+ALTER TABLE capsules DROP COLUMN duration_ms;
+
+ALTER TABLE sessions DROP COLUMN expires;
+ALTER TABLE sessions DROP COLUMN impersonator;
+
+ALTER TABLE users DROP COLUMN activated_on;
+ALTER TABLE users DROP COLUMN approver;
+
+DROP TABLE organizations_members_join;
+DROP TABLE organizations;
+
+DROP TYPE audit_action;
+DROP TYPE approval_status;
+DROP TYPE organization_role;
+
+UPDATE capsules SET video_uploaded = 'idle' WHERE video_uploaded IN ('disabled', 'waiting', 'failed');
+UPDATE capsules SET produced = 'idle' WHERE produced IN ('disabled', 'waiting', 'failed');
+UPDATE capsules SET published = 'idle' WHERE published IN ('disabled', 'waiting', 'failed');
+ALTER TYPE task_status RENAME TO task_status_old;
+CREATE TYPE task_status AS ENUM ('idle', 'running', 'done');
+ALTER TABLE capsules ALTER COLUMN video_uploaded TYPE task_status USING video_uploaded::text::task_status;
+ALTER TABLE capsules ALTER COLUMN produced TYPE task_status USING produced::text::task_status;
+ALTER TABLE capsules ALTER COLUMN published TYPE task_status USING published::text::task_status;
+DROP TYPE task_status_old;
+
+COMMIT;
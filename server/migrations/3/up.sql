@@ -0,0 +1,162 @@
+-- Organizations, approvals, trash, audit log, comments, tags, folders, data exports, pending
+-- invitations and ownership transfers. Every step can be run again.
+ALTER TYPE task_status ADD VALUE IF NOT EXISTS 'disabled' BEFORE 'idle';
+ALTER TYPE task_status ADD VALUE IF NOT EXISTS 'waiting' BEFORE 'running';
+ALTER TYPE task_status ADD VALUE IF NOT EXISTS 'failed' AFTER 'done';
+
+BEGIN;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'organization_role') THEN
+        CREATE TYPE organization_role AS ENUM ('member', 'admin');
+    END IF;
+
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'approval_status') THEN
+        CREATE TYPE approval_status AS ENUM ('unrequested', 'pending', 'approved', 'rejected');
+    END IF;
+
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'audit_action') THEN
+        CREATE TYPE audit_action AS ENUM (
+            'login',
+            'logout',
+            'change_password',
+            'change_email',
+            'invite',
+            'deinvite',
+            'change_role',
+            'delete_capsule',
+            'restore_capsule',
+            'purge_capsule',
+            'delete_project',
+            'accept_transfer',
+            'publish',
+            'unpublish',
+            'admin_delete_user',
+            'admin_invite_user',
+            'admin_change_plan',
+            'admin_change_disk_quota',
+            'admin_set_activated',
+            'admin_reset_password',
+            'admin_resend_activation',
+            'admin_start_impersonation',
+            'admin_stop_impersonation',
+            'admin_reset_tasks',
+            'admin_rerun_tasks',
+            'admin_set_approver'
+        );
+    END IF;
+END $$;
+
+CREATE TABLE IF NOT EXISTS organizations (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS organizations_members_join (
+    id SERIAL PRIMARY KEY,
+    organizations_id INT NOT NULL REFERENCES organizations ON DELETE CASCADE,
+    members_id INT NOT NULL REFERENCES users ON DELETE CASCADE,
+    organization_role organization_role NOT NULL
+);
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS approver BOOL NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS activated_on TIMESTAMP;
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS impersonator INT;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS expires TIMESTAMP;
+
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS duration_ms INT NOT NULL DEFAULT 0;
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS organization INT;
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS approval approval_status NOT NULL DEFAULT 'unrequested';
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS approval_required BOOL NOT NULL DEFAULT FALSE;
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS approval_comment VARCHAR;
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS deleted TIMESTAMP;
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS created TIMESTAMP NOT NULL DEFAULT NOW();
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS produced_on TIMESTAMP;
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS published_on TIMESTAMP;
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS output_settings JSON;
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS slide_duration_ms INT;
+ALTER TABLE capsules ADD COLUMN IF NOT EXISTS audio_tracks JSON NOT NULL DEFAULT '[]'::json;
+
+ALTER TABLE capsules ALTER COLUMN duration_ms DROP DEFAULT;
+ALTER TABLE capsules ALTER COLUMN approval DROP DEFAULT;
+ALTER TABLE capsules ALTER COLUMN approval_required DROP DEFAULT;
+ALTER TABLE capsules ALTER COLUMN created DROP DEFAULT;
+ALTER TABLE capsules ALTER COLUMN audio_tracks DROP DEFAULT;
+ALTER TABLE users ALTER COLUMN approver DROP DEFAULT;
+
+CREATE TABLE IF NOT EXISTS audit_events (
+    id SERIAL PRIMARY KEY,
+    action audit_action NOT NULL,
+    actor_id INT,
+    actor VARCHAR,
+    target VARCHAR,
+    details VARCHAR,
+    ip VARCHAR,
+    time TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS comments (
+    id SERIAL PRIMARY KEY,
+    content VARCHAR NOT NULL,
+    gos INT,
+    slide VARCHAR,
+    time_ms INT,
+    resolved BOOL NOT NULL,
+    created TIMESTAMP NOT NULL,
+    capsule INT NOT NULL REFERENCES capsules (id) ON DELETE CASCADE,
+    author INT NOT NULL REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS tags (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    owner INT NOT NULL REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS tags_capsules_join (
+    id SERIAL PRIMARY KEY,
+    tags_id INT NOT NULL REFERENCES tags ON DELETE CASCADE,
+    capsules_id INT NOT NULL REFERENCES capsules ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS folders (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    parent INT,
+    owner INT NOT NULL REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS folders_capsules_join (
+    id SERIAL PRIMARY KEY,
+    folders_id INT NOT NULL REFERENCES folders ON DELETE CASCADE,
+    capsules_id INT NOT NULL REFERENCES capsules ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS data_exports (
+    id SERIAL PRIMARY KEY,
+    key VARCHAR NOT NULL UNIQUE,
+    status task_status NOT NULL,
+    created TIMESTAMP NOT NULL,
+    expires TIMESTAMP NOT NULL,
+    owner INT NOT NULL REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS pending_invitations (
+    id SERIAL PRIMARY KEY,
+    email VARCHAR NOT NULL,
+    role role NOT NULL,
+    key VARCHAR NOT NULL UNIQUE,
+    expires TIMESTAMP NOT NULL,
+    capsule INT NOT NULL REFERENCES capsules (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS ownership_transfers (
+    id SERIAL PRIMARY KEY,
+    capsule INT NOT NULL REFERENCES capsules (id) ON DELETE CASCADE,
+    sender INT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    recipient INT NOT NULL REFERENCES users (id) ON DELETE CASCADE
+);
+
+COMMIT;
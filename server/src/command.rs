@@ -1,6 +1,6 @@
 //! This module helps us running commands.
 
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use std::process::{Command, Output};
 use std::result::Result as StdResult;
 
@@ -9,6 +9,7 @@ use uuid::Uuid;
 use rayon::prelude::*;
 
 use rocket::http::Status;
+use rocket::serde::json::from_str;
 
 use crate::config::Config;
 use crate::{Error, Result};
@@ -92,14 +93,15 @@ pub fn count_pages<P: AsRef<Path>>(input: P) -> Result<u32> {
     Ok(count)
 }
 
-/// Exports all slides (or one) from pdf to png.
+/// Exports all slides (or one) from pdf to png, with the specified size.
 pub fn export_slides<P: AsRef<Path>, Q: AsRef<Path> + Send + Sync>(
     config: &Config,
     input: P,
     output: Q,
     page: Option<i32>,
+    size: &str,
 ) -> Result<Vec<Uuid>> {
-    let pdf_target_size = config.pdf_target_size.clone();
+    let pdf_target_size = size.to_string();
     let pdf_target_density = config.pdf_target_density.clone();
     match page {
         Some(x) => {
@@ -171,3 +173,349 @@ pub fn export_slides<P: AsRef<Path>, Q: AsRef<Path> + Send + Sync>(
         }
     }
 }
+
+/// Converts an office presentation (pptx, odp...) to a PDF file in the output directory.
+///
+/// Returns the path of the PDF file.
+pub fn office_to_pdf<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> Result<PathBuf> {
+    let input = input.as_ref();
+    let output = output.as_ref();
+
+    run_command(&vec![
+        "../scripts/psh",
+        "office-to-pdf",
+        input.to_str().ok_or(Error(Status::InternalServerError))?,
+        output.to_str().ok_or(Error(Status::InternalServerError))?,
+    ])?;
+
+    let stem = input
+        .file_stem()
+        .ok_or(Error(Status::InternalServerError))?;
+
+    Ok(output.join(stem).with_extension("pdf"))
+}
+
+/// Extracts the speaker notes of each slide of an office presentation.
+pub fn office_notes<P: AsRef<Path>>(input: P) -> Result<Vec<String>> {
+    let output = run_command(&vec![
+        "../scripts/psh",
+        "office-notes",
+        input
+            .as_ref()
+            .to_str()
+            .ok_or(Error(Status::InternalServerError))?,
+    ])?;
+
+    from_str(std::str::from_utf8(&output.stdout)?).map_err(|_| Error(Status::InternalServerError))
+}
+
+/// Extracts the notes of each page of a PDF file from its annotations, and from its text if
+/// `with_text` is true.
+pub fn pdf_notes<P: AsRef<Path>>(input: P, with_text: bool) -> Result<Vec<String>> {
+    let output = run_command(&vec![
+        "../scripts/psh",
+        "pdf-notes",
+        input
+            .as_ref()
+            .to_str()
+            .ok_or(Error(Status::InternalServerError))?,
+        if with_text { "true" } else { "false" },
+    ])?;
+
+    from_str(std::str::from_utf8(&output.stdout)?).map_err(|_| Error(Status::InternalServerError))
+}
+
+/// The extensions of the images that can be imported as slides.
+const IMAGE_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "gif", "bmp", "tif", "tiff"];
+
+/// The largest total size of the files extracted from the zip archives of one import.
+pub const MAX_UNZIPPED_SIZE: u64 = 2 << 30;
+
+/// Checks the entries of a zip archive before extracting it, and returns their total size.
+///
+/// `details` is the output of `unzip -Z` and `names` the output of `unzip -Z1` for the archive.
+/// Archives containing symbolic links or paths leaving the output directory are rejected, since
+/// they could make the import read or write any file of the server.
+fn check_zip_entries(details: &str, names: &str) -> Result<u64> {
+    for name in names.lines() {
+        let escapes = Path::new(name)
+            .components()
+            .any(|x| !matches!(x, Component::Normal(_) | Component::CurDir));
+
+        if escapes {
+            return Err(Error(Status::BadRequest));
+        }
+    }
+
+    // The first two lines are the name and size of the archive, and the last one is a summary.
+    let lines = details.lines().collect::<Vec<_>>();
+    let entries = lines.get(2..lines.len().saturating_sub(1)).unwrap_or(&[]);
+
+    let mut size = 0u64;
+
+    for entry in entries {
+        let fields = entry.split_whitespace().collect::<Vec<_>>();
+
+        if fields.first().map(|x| x.starts_with('l')).unwrap_or(true) {
+            return Err(Error(Status::BadRequest));
+        }
+
+        let entry_size = fields
+            .get(3)
+            .and_then(|x| x.parse::<u64>().ok())
+            .ok_or(Error(Status::BadRequest))?;
+
+        size = size.saturating_add(entry_size);
+    }
+
+    Ok(size)
+}
+
+/// Extracts the images of a zip archive in a directory.
+///
+/// Returns the extracted images with their path in the archive, the hidden files and directories
+/// being skipped. The size of the extracted files is taken from `budget`, and the import fails
+/// with `413 Payload Too Large` when it runs out.
+pub fn unzip_images<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    budget: &mut u64,
+) -> Result<Vec<(String, PathBuf)>> {
+    let input = input
+        .as_ref()
+        .to_str()
+        .ok_or(Error(Status::InternalServerError))?;
+
+    let output = output.as_ref();
+    std::fs::create_dir_all(output)?;
+
+    let details = run_command(&vec!["unzip", "-Z", input])?;
+    let names = run_command(&vec!["unzip", "-Z1", input])?;
+    let size = check_zip_entries(
+        std::str::from_utf8(&details.stdout)?,
+        std::str::from_utf8(&names.stdout)?,
+    )?;
+
+    if size > *budget {
+        return Err(Error(Status::PayloadTooLarge));
+    }
+
+    run_command(&vec![
+        "unzip",
+        "-qq",
+        "-o",
+        input,
+        "-d",
+        output.to_str().ok_or(Error(Status::InternalServerError))?,
+    ])?;
+
+    let mut images = vec![];
+    let mut directories = vec![output.to_path_buf()];
+    let mut extracted = 0u64;
+
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+
+            // The sizes in the archive are not trusted, the extracted files are measured.
+            if file_type.is_file() {
+                extracted = extracted.saturating_add(entry.metadata()?.len());
+            }
+
+            match path.file_name().and_then(|x| x.to_str()) {
+                // Hidden files are metadata added by some archivers, e.g. `._slide.png` or
+                // `__MACOSX` on macOS.
+                Some(name) if !name.starts_with('.') && name != "__MACOSX" => (),
+                _ => continue,
+            }
+
+            if file_type.is_dir() {
+                directories.push(path);
+                continue;
+            }
+
+            // Only regular files are imported, a link could point to any file of the server.
+            if !file_type.is_file() {
+                continue;
+            }
+
+            let extension = path
+                .extension()
+                .and_then(|x| x.to_str())
+                .map(|x| x.to_lowercase());
+
+            if extension.map(|x| IMAGE_EXTENSIONS.contains(&x.as_str())) == Some(true) {
+                let name = path
+                    .strip_prefix(output)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .to_string();
+
+                images.push((name, path));
+            }
+        }
+    }
+
+    if extracted > *budget {
+        return Err(Error(Status::PayloadTooLarge));
+    }
+
+    *budget -= extracted;
+    Ok(images)
+}
+
+/// Converts images to slides of the specified size, in parallel.
+pub fn import_images<P: AsRef<Path> + Sync, Q: AsRef<Path> + Send + Sync>(
+    config: &Config,
+    inputs: &[P],
+    output: Q,
+    size: &str,
+) -> Result<Vec<Uuid>> {
+    inputs
+        .par_iter()
+        .map(|input| {
+            let uuid = Uuid::new_v4();
+            let output = output.as_ref().join(format!("{}.png", uuid));
+
+            run_command(&vec![
+                "../scripts/psh",
+                "pdf-to-png",
+                input
+                    .as_ref()
+                    .to_str()
+                    .ok_or(Error(Status::InternalServerError))?,
+                output.to_str().ok_or(Error(Status::InternalServerError))?,
+                &config.pdf_target_density,
+                size,
+            ])?;
+
+            Ok(uuid)
+        })
+        .collect()
+}
+
+/// Renders markdown slides to a PDF file.
+pub fn markdown_to_pdf<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> Result<()> {
+    run_command(&vec![
+        "../scripts/psh",
+        "markdown-to-pdf",
+        input
+            .as_ref()
+            .to_str()
+            .ok_or(Error(Status::InternalServerError))?,
+        output
+            .as_ref()
+            .to_str()
+            .ok_or(Error(Status::InternalServerError))?,
+    ])?;
+
+    Ok(())
+}
+
+/// Detects the slide changes of a video, and saves a still of each slide in a directory.
+///
+/// Returns the time of each slide in milliseconds with the path of its still.
+pub fn split_scenes<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    threshold: f32,
+) -> Result<Vec<(i32, PathBuf)>> {
+    let output = output.as_ref();
+    std::fs::create_dir_all(output)?;
+
+    let result = run_command(&vec![
+        "../scripts/psh",
+        "split-scenes",
+        input
+            .as_ref()
+            .to_str()
+            .ok_or(Error(Status::InternalServerError))?,
+        output.to_str().ok_or(Error(Status::InternalServerError))?,
+        &threshold.to_string(),
+    ])?;
+
+    std::str::from_utf8(&result.stdout)?
+        .lines()
+        .enumerate()
+        .map(|(i, line)| -> Result<(i32, PathBuf)> {
+            Ok((line.trim().parse()?, output.join(format!("{}.png", i))))
+        })
+        .collect()
+}
+
+/// Converts the audio of a file to the format of the background musics.
+pub fn import_audio<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> Result<()> {
+    let input = input
+        .as_ref()
+        .to_str()
+        .ok_or(Error(Status::InternalServerError))?;
+
+    let video_type = run_command(&vec!["../scripts/psh", "video-type", input])?;
+
+    match std::str::from_utf8(&video_type.stdout)?.trim() {
+        "audio_only" | "audio_and_video" => (),
+        _ => return Err(Error(Status::UnsupportedMediaType)),
+    }
+
+    run_command(&vec![
+        "../scripts/psh",
+        "import-audio",
+        input,
+        output
+            .as_ref()
+            .to_str()
+            .ok_or(Error(Status::InternalServerError))?,
+    ])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The output of `unzip -Z` for an archive containing `entries`.
+    fn details(entries: &[&str]) -> String {
+        let mut lines = vec![
+            String::from("Archive:  slides.zip"),
+            format!(
+                "Zip file size: 454 bytes, number of entries: {}",
+                entries.len()
+            ),
+        ];
+        lines.extend(entries.iter().map(|x| x.to_string()));
+        lines.push(String::from(
+            "3 files, 14 bytes uncompressed, 14 bytes compressed:  0.0%",
+        ));
+        lines.join("\n")
+    }
+
+    #[test]
+    fn zip_entries_are_summed() {
+        let details = details(&[
+            "drwxr-xr-x  3.0 unx        0 bx stor 26-Oct-19 00:38 d/",
+            "-rw-r--r--  3.0 unx     1000 tx defN 26-Oct-19 00:38 d/a.png",
+            "-rw-a--     2.0 fat      234 bx defN 26-Oct-19 00:38 b.png",
+        ]);
+
+        let size = check_zip_entries(&details, "d/\nd/a.png\nb.png\n").unwrap();
+        assert_eq!(size, 1234);
+    }
+
+    #[test]
+    fn zip_entries_reject_symlinks() {
+        let details = details(&["lrwxrwxrwx  3.0 unx       11 bx stor 26-Oct-19 00:38 a.png"]);
+        assert!(check_zip_entries(&details, "a.png\n").is_err());
+    }
+
+    #[test]
+    fn zip_entries_reject_path_traversal() {
+        let details = details(&[]);
+        assert!(check_zip_entries(&details, "../a.png\n").is_err());
+        assert!(check_zip_entries(&details, "d/../../a.png\n").is_err());
+        assert!(check_zip_entries(&details, "/etc/a.png\n").is_err());
+        assert!(check_zip_entries(&details, "d/./a.png\n").is_ok());
+    }
+}
@@ -29,6 +29,26 @@ fn default_videos_path() -> PathBuf {
     PathBuf::from("videos")
 }
 
+fn default_exports_path() -> PathBuf {
+    PathBuf::from("exports")
+}
+
+fn default_export_validity_hours() -> i64 {
+    48
+}
+
+fn default_trash_retention_days() -> i64 {
+    30
+}
+
+fn default_invitation_validity_days() -> i64 {
+    14
+}
+
+fn default_impersonation_minutes() -> i64 {
+    30
+}
+
 fn default_socket_listen() -> String {
     String::from("localhost:8001")
 }
@@ -81,6 +101,10 @@ fn default_registration_disabled() -> bool {
     false
 }
 
+fn default_approval_required() -> bool {
+    false
+}
+
 #[cfg(feature = "git")]
 fn default_commit() -> Option<&'static str> {
     Some(compile_time_run::run_command_str!(
@@ -144,6 +168,26 @@ pub struct Config {
     #[serde(default = "default_videos_path")]
     pub videos_path: PathBuf,
 
+    /// The path where the exports of the data of users are stored.
+    #[serde(default = "default_exports_path")]
+    pub exports_path: PathBuf,
+
+    /// The number of hours during which an export of the data of a user can be downloaded.
+    #[serde(default = "default_export_validity_hours")]
+    pub export_validity_hours: i64,
+
+    /// The number of days during which a deleted capsule can be restored from the trash.
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: i64,
+
+    /// The number of days during which a person invited to a capsule can create their account.
+    #[serde(default = "default_invitation_validity_days")]
+    pub invitation_validity_days: i64,
+
+    /// The number of minutes during which an admin can view the website as a user.
+    #[serde(default = "default_impersonation_minutes")]
+    pub impersonation_minutes: i64,
+
     /// The url to which the websocket server must listen.
     #[serde(default = "default_socket_listen")]
     pub socket_listen: String,
@@ -164,6 +208,10 @@ pub struct Config {
     #[serde(default = "default_registration_disabled")]
     pub registration_disabled: bool,
 
+    /// Whether capsules must be approved before being published.
+    #[serde(default = "default_approval_required")]
+    pub approval_required: bool,
+
     /// The domain on which the cookies should be set.
     pub cookie_domain: Option<String>,
 
@@ -0,0 +1,23 @@
+//! This module contains the approval status enum, representing the different steps of the review
+//! of a capsule before its publication.
+
+use ergol::prelude::*;
+
+use serde::{Deserialize, Serialize};
+
+/// The different states in which the approval of a capsule can be.
+#[derive(PgEnum, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalStatus {
+    /// The review of the capsule has not been requested.
+    Unrequested,
+
+    /// The review has been requested and waits for an approver.
+    Pending,
+
+    /// The capsule has been approved and can be published.
+    Approved,
+
+    /// The capsule has been rejected.
+    Rejected,
+}
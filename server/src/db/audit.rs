@@ -0,0 +1,217 @@
+//! This module contains the audit log, recording who did what on polymny.
+
+use std::net::IpAddr;
+
+use chrono::{NaiveDateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+use ergol::prelude::*;
+use ergol::query::Filter;
+
+use rocket::serde::json::{json, Value};
+
+use crate::db::user::User;
+use crate::{Db, Result};
+
+/// The number of audit events returned per page.
+const PAGE_SIZE: usize = 50;
+
+/// The different actions that are recorded in the audit log.
+#[derive(Debug, Copy, Clone, PgEnum, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// A user logged in.
+    Login,
+
+    /// A user logged out.
+    Logout,
+
+    /// A user changed their password.
+    ChangePassword,
+
+    /// A user changed their email address.
+    ChangeEmail,
+
+    /// A user was invited to a capsule.
+    Invite,
+
+    /// A user was removed from a capsule.
+    Deinvite,
+
+    /// The role of a user on a capsule was changed.
+    ChangeRole,
+
+    /// A capsule was moved to the trash.
+    DeleteCapsule,
+
+    /// A capsule was restored from the trash.
+    RestoreCapsule,
+
+    /// A capsule was deleted from the trash for good.
+    PurgeCapsule,
+
+    /// A project was deleted.
+    DeleteProject,
+
+    /// A user accepted the ownership of a capsule from another user.
+    AcceptTransfer,
+
+    /// A capsule was published.
+    Publish,
+
+    /// A capsule was unpublished.
+    Unpublish,
+
+    /// An admin deleted a user.
+    AdminDeleteUser,
+
+    /// An admin invited a user.
+    AdminInviteUser,
+
+    /// An admin changed the plan of a user.
+    AdminChangePlan,
+
+    /// An admin changed the disk quota of a user.
+    AdminChangeDiskQuota,
+
+    /// An admin activated or deactivated a user.
+    AdminSetActivated,
+
+    /// An admin forced a user to reset their password.
+    AdminResetPassword,
+
+    /// An admin sent the activation email of a user again.
+    AdminResendActivation,
+
+    /// An admin started viewing the website as a user.
+    AdminStartImpersonation,
+
+    /// An admin stopped viewing the website as a user.
+    AdminStopImpersonation,
+
+    /// An admin reset the tasks of a capsule.
+    AdminResetTasks,
+
+    /// An admin ran the production or the publication of capsules again.
+    AdminRerunTasks,
+
+    /// An admin allowed or forbade a user to approve capsules.
+    AdminSetApprover,
+}
+
+/// An event of the audit log.
+///
+/// The actor is stored by id and username rather than as a foreign key so that the event
+/// survives the deletion of the user.
+#[ergol]
+pub struct AuditEvent {
+    /// The id of the event.
+    #[id]
+    pub id: i32,
+
+    /// The action that was performed.
+    pub action: AuditAction,
+
+    /// The id of the user that performed the action, if any.
+    pub actor_id: Option<i32>,
+
+    /// The username of the user that performed the action, if any.
+    pub actor: Option<String>,
+
+    /// A description of the target of the action, e.g. `capsule:<id>` or `user:<username>`.
+    pub target: Option<String>,
+
+    /// Additional details about the action, e.g. the new role of a user.
+    pub details: Option<String>,
+
+    /// The ip address from which the action was performed, if known.
+    pub ip: Option<String>,
+
+    /// The time when the action was performed.
+    pub time: NaiveDateTime,
+}
+
+impl AuditEvent {
+    /// Records a new event in the audit log.
+    pub async fn log(
+        action: AuditAction,
+        actor: Option<&User>,
+        target: Option<String>,
+        details: Option<String>,
+        ip: Option<IpAddr>,
+        db: &Db,
+    ) -> Result<AuditEvent> {
+        Ok(AuditEvent::create(
+            action,
+            actor.map(|x| x.id),
+            actor.map(|x| x.username.clone()),
+            target,
+            details,
+            ip.map(|x| x.to_string()),
+            Utc::now().naive_utc(),
+        )
+        .save(db)
+        .await?)
+    }
+
+    /// Returns a page of events matching the filters, most recent first.
+    pub async fn search(
+        action: Option<AuditAction>,
+        actor: Option<String>,
+        target: Option<String>,
+        since: Option<NaiveDateTime>,
+        until: Option<NaiveDateTime>,
+        page: i32,
+        db: &Db,
+    ) -> Result<Vec<AuditEvent>> {
+        let mut filters: Vec<Filter> = vec![];
+
+        if let Some(action) = action {
+            filters.push(audit_event::action::eq(action));
+        }
+
+        if let Some(actor) = actor {
+            filters.push(audit_event::actor::eq(Some(actor)));
+        }
+
+        if let Some(target) = target {
+            filters.push(audit_event::target::eq(Some(target)));
+        }
+
+        if let Some(since) = since {
+            filters.push(audit_event::time::geq(since));
+        }
+
+        if let Some(until) = until {
+            filters.push(audit_event::time::leq(until));
+        }
+
+        let mut query = AuditEvent::select();
+
+        if let Some(filter) = filters.into_iter().reduce(|acc, x| acc.and(x)) {
+            query = query.filter(filter);
+        }
+
+        Ok(query
+            .order_by(audit_event::time::descend())
+            .limit(PAGE_SIZE)
+            .offset((page.max(0) as usize) * PAGE_SIZE)
+            .execute(db)
+            .await?)
+    }
+
+    /// Returns a json representation of the event.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "action": self.action,
+            "actor_id": self.actor_id,
+            "actor": self.actor,
+            "target": self.target,
+            "details": self.details,
+            "ip": self.ip,
+            "time": self.time.timestamp(),
+        })
+    }
+}
@@ -1,9 +1,11 @@
 //! This module contains everything that manage the capsule in the database.
-use chrono::{NaiveDateTime, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
 use std::default::Default;
 
 use ergol::prelude::*;
-use ergol::tokio_postgres::types::Json;
+use ergol::tokio_postgres::types::{Json, ToSql};
+
+use tokio::fs::remove_dir_all;
 
 use uuid::Uuid;
 
@@ -14,8 +16,11 @@ use tungstenite::Message;
 use rocket::http::Status;
 use rocket::serde::json::{json, Value};
 
+use crate::config::Config;
+use crate::db::approval::ApprovalStatus;
+use crate::db::project::Project;
 use crate::db::task_status::TaskStatus;
-use crate::db::user::User;
+use crate::db::user::{Plan, User};
 use crate::websockets::WebSockets;
 use crate::{Db, Error, Result, HARSH};
 
@@ -26,6 +31,9 @@ pub enum Role {
     /// The user has read access to the capsule.
     Read,
 
+    /// The user can review the capsule and comment it, but not edit it.
+    Comment,
+
     /// The user has write access to the capsule.
     Write,
 
@@ -33,6 +41,52 @@ pub enum Role {
     Owner,
 }
 
+/// The duration of a slide without record when neither the slide nor the capsule specify one.
+pub const DEFAULT_SLIDE_DURATION_MS: i32 = 3000;
+
+/// The shortest duration allowed for a slide without record.
+pub const MIN_SLIDE_DURATION_MS: i32 = 500;
+
+/// The longest duration allowed for a slide without record.
+pub const MAX_SLIDE_DURATION_MS: i32 = 600_000;
+
+/// Checks that a duration is allowed for a slide without record.
+pub fn check_slide_duration(duration_ms: i32) -> Result<()> {
+    if !(MIN_SLIDE_DURATION_MS..=MAX_SLIDE_DURATION_MS).contains(&duration_ms) {
+        return Err(Error(Status::BadRequest));
+    }
+
+    Ok(())
+}
+
+/// Checks that the default duration of a capsule and the durations of the slides of its
+/// structure are allowed.
+pub fn check_slide_durations(default: Option<i32>, structure: &[Gos]) -> Result<()> {
+    let durations = structure
+        .iter()
+        .flat_map(|gos| gos.slides.iter())
+        .filter_map(|slide| slide.duration_ms);
+
+    for duration_ms in default.into_iter().chain(durations) {
+        check_slide_duration(duration_ms)?;
+    }
+
+    Ok(())
+}
+
+/// Checks that the transitions between the slides and the goss of a structure are allowed.
+pub fn check_transitions(structure: &[Gos]) -> Result<()> {
+    for gos in structure {
+        gos.transition.check()?;
+
+        for slide in &gos.slides {
+            slide.transition.check()?;
+        }
+    }
+
+    Ok(())
+}
+
 /// A slide with its prompt.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Slide {
@@ -44,6 +98,28 @@ pub struct Slide {
 
     /// The prompt associated to the slide.
     pub prompt: String,
+
+    /// How long the slide is shown when its gos has no record, the default duration of the
+    /// capsule if none.
+    #[serde(default)]
+    pub duration_ms: Option<i32>,
+
+    /// The transition to the next slide of the gos.
+    #[serde(default)]
+    pub transition: Transition,
+}
+
+impl Slide {
+    /// Creates a new slide without extra nor specific duration.
+    pub fn new(uuid: Uuid, prompt: String) -> Slide {
+        Slide {
+            uuid,
+            extra: None,
+            prompt,
+            duration_ms: None,
+            transition: Transition::cut(),
+        }
+    }
 }
 
 /// The anchor of the webcam.
@@ -189,16 +265,16 @@ pub struct Event {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Fade {
     /// duration of video fade in
-    vfadein: Option<i32>,
+    pub vfadein: Option<i32>,
 
     /// duration of video fade out
-    vfadeout: Option<i32>,
+    pub vfadeout: Option<i32>,
 
     /// duration of audio fade in
-    afadein: Option<i32>,
+    pub afadein: Option<i32>,
 
     /// duration of audio fade out
-    afadeout: Option<i32>,
+    pub afadeout: Option<i32>,
 }
 
 impl Fade {
@@ -219,6 +295,72 @@ impl Default for Fade {
     }
 }
 
+/// The different kinds of transition.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionType {
+    /// The next content replaces the previous one instantly.
+    Cut,
+
+    /// The previous content dissolves into the next one.
+    Crossfade,
+
+    /// The previous content fades to black, then the next one fades in.
+    FadeBlack,
+
+    /// The next content slides in from the right.
+    SlideLeft,
+
+    /// The next content slides in from the left.
+    SlideRight,
+
+    /// The next content is revealed from right to left.
+    WipeLeft,
+
+    /// The next content is revealed from left to right.
+    WipeRight,
+}
+
+/// The longest duration allowed for a transition.
+pub const MAX_TRANSITION_DURATION_MS: i32 = 5000;
+
+/// A transition from a slide or a gos to the next one.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Transition {
+    /// The kind of the transition.
+    pub ty: TransitionType,
+
+    /// The duration of the transition in milliseconds, ignored for cuts.
+    pub duration_ms: i32,
+}
+
+impl Transition {
+    /// Returns the default transition, which is a cut.
+    pub fn cut() -> Transition {
+        Transition {
+            ty: TransitionType::Cut,
+            duration_ms: 0,
+        }
+    }
+
+    /// Checks that the duration of the transition is allowed.
+    pub fn check(&self) -> Result<()> {
+        if self.ty != TransitionType::Cut
+            && (self.duration_ms <= 0 || self.duration_ms > MAX_TRANSITION_DURATION_MS)
+        {
+            return Err(Error(Status::BadRequest));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Transition {
+    fn default() -> Transition {
+        Transition::cut()
+    }
+}
+
 /// The different pieces of information that we collect about a gos.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Gos {
@@ -237,6 +379,10 @@ pub struct Gos {
     /// Video/audio fade options
     #[serde(default)]
     pub fade: Fade,
+
+    /// The transition to the next gos.
+    #[serde(default)]
+    pub transition: Transition,
 }
 
 impl Gos {
@@ -248,6 +394,132 @@ impl Gos {
             events: vec![],
             webcam_settings: WebcamSettings::default(),
             fade: Fade::none(),
+            transition: Transition::cut(),
+        }
+    }
+
+    /// Creates a gos containing only one slide, without record.
+    pub fn from_slide(uuid: Uuid, prompt: String) -> Gos {
+        Gos {
+            slides: vec![Slide::new(uuid, prompt)],
+            ..Gos::new()
+        }
+    }
+}
+
+/// The format of the video of a capsule.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OutputSettings {
+    /// The width of the video in pixels.
+    pub width: u32,
+
+    /// The height of the video in pixels.
+    pub height: u32,
+
+    /// The framerate of the video.
+    pub fps: u32,
+}
+
+impl OutputSettings {
+    /// Returns the default format of the videos of the instance.
+    pub fn from_config(config: &Config) -> OutputSettings {
+        let size = config
+            .pdf_target_size
+            .split('x')
+            .map(|x| x.parse::<u32>())
+            .collect::<Vec<_>>();
+
+        let (width, height) = match size.as_slice() {
+            [Ok(width), Ok(height)] => (*width, *height),
+            _ => (1920, 1080),
+        };
+
+        OutputSettings {
+            width,
+            height,
+            fps: 25,
+        }
+    }
+
+    /// Returns the size of the video, as expected by imagemagick and ffmpeg.
+    pub fn size(&self) -> String {
+        format!("{}x{}", self.width, self.height)
+    }
+
+    /// Checks that the format is valid and allowed by a plan.
+    ///
+    /// The sides must be even for the video to be encoded, and the longest side and the
+    /// framerate are limited by the plan.
+    pub fn check(&self, plan: Plan) -> Result<()> {
+        let valid_side = |x: u32| (240..=plan.max_output_size()).contains(&x) && x % 2 == 0;
+
+        if !valid_side(self.width)
+            || !valid_side(self.height)
+            || self.fps == 0
+            || self.fps > plan.max_output_fps()
+        {
+            return Err(Error(Status::BadRequest));
+        }
+
+        Ok(())
+    }
+}
+
+/// The loudest volume allowed for an audio track.
+pub const MAX_AUDIO_TRACK_VOLUME: f32 = 2.0;
+
+/// A background music played during some goss of a capsule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioTrack {
+    /// The uuid of the audio file.
+    pub uuid: Uuid,
+
+    /// The name of the file that was uploaded.
+    pub name: String,
+
+    /// The first gos during which the track plays, the first gos of the capsule if none.
+    #[serde(default)]
+    pub start_gos: Option<i32>,
+
+    /// The last gos during which the track plays, the last gos of the capsule if none.
+    #[serde(default)]
+    pub end_gos: Option<i32>,
+
+    /// The volume of the track, 1.0 keeping the volume of the file.
+    pub volume: f32,
+
+    /// Whether the track starts again when it ends before its last gos.
+    pub looping: bool,
+
+    /// Whether the track is lowered while the records are speaking.
+    pub ducking: bool,
+}
+
+impl AudioTrack {
+    /// Creates a new audio track played quietly during the whole capsule.
+    pub fn new(uuid: Uuid, name: String) -> AudioTrack {
+        AudioTrack {
+            uuid,
+            name,
+            start_gos: None,
+            end_gos: None,
+            volume: 0.3,
+            looping: true,
+            ducking: true,
+        }
+    }
+
+    /// Checks that the volume and the range of goss of the track are valid.
+    pub fn check(&self) -> Result<()> {
+        if !(0.0..=MAX_AUDIO_TRACK_VOLUME).contains(&self.volume) {
+            return Err(Error(Status::BadRequest));
+        }
+
+        match (self.start_gos, self.end_gos) {
+            (Some(start), _) if start < 0 => Err(Error(Status::BadRequest)),
+            (_, Some(end)) if end < 0 => Err(Error(Status::BadRequest)),
+            (Some(start), Some(end)) if start > end => Err(Error(Status::BadRequest)),
+            _ => Ok(()),
         }
     }
 }
@@ -273,8 +545,8 @@ pub struct Capsule {
     #[id]
     pub id: i32,
 
-    /// The project name.
-    pub project: String,
+    /// The id of the project of the capsule, if any.
+    pub project: Option<i32>,
 
     /// The name of the capsule.
     pub name: String,
@@ -315,6 +587,41 @@ pub struct Capsule {
     /// duration of produced video in ms
     pub duration_ms: i32,
 
+    /// The id of the organization that owns the capsule, if any.
+    pub organization: Option<i32>,
+
+    /// The state of the review of the capsule before its publication.
+    pub approval: ApprovalStatus,
+
+    /// Whether the capsule must be approved before being published, even if it is not required
+    /// for the whole instance.
+    pub approval_required: bool,
+
+    /// The comment left by the approver who approved or rejected the capsule.
+    pub approval_comment: Option<String>,
+
+    /// The time when the capsule was moved to the trash, if it was.
+    pub deleted: Option<NaiveDateTime>,
+
+    /// The time when the capsule was created.
+    pub created: NaiveDateTime,
+
+    /// The last time the capsule was successfully produced.
+    pub produced_on: Option<NaiveDateTime>,
+
+    /// The last time the capsule was successfully published.
+    pub published_on: Option<NaiveDateTime>,
+
+    /// The format of the video of the capsule, the default format of the instance if none.
+    pub output_settings: Option<Json<OutputSettings>>,
+
+    /// The duration of the slides without record that do not specify one, the default of the
+    /// instance if none.
+    pub slide_duration_ms: Option<i32>,
+
+    /// The background musics of the capsule.
+    pub audio_tracks: Json<Vec<AudioTrack>>,
+
     /// The user that has rights on the capsule.
     #[many_to_many(capsules, Role)]
     pub users: User,
@@ -331,8 +638,8 @@ impl Capsule {
         let project = project.into();
         let name = name.into();
 
-        let capsule = Capsule::create(
-            project,
+        let mut capsule = Capsule::create(
+            None,
             name,
             TaskStatus::Idle,
             None,
@@ -346,15 +653,143 @@ impl Capsule {
             Utc::now().naive_utc(),
             0,
             0,
+            None,
+            ApprovalStatus::Unrequested,
+            false,
+            None,
+            None,
+            Utc::now().naive_utc(),
+            None,
+            None,
+            None,
+            None,
+            Json(vec![]),
         )
         .save(&db)
         .await?;
 
         capsule.add_user(owner, Role::Owner, db).await?;
 
+        if !project.is_empty() {
+            let mut project = Project::get_or_create(&project, owner, db).await?;
+            project.add_capsule(&mut capsule, db).await?;
+        }
+
         Ok(capsule)
     }
 
+    /// Returns the name of the project of the capsule, or an empty string if it has none.
+    pub async fn project_name(&self, db: &Db) -> Result<String> {
+        let project = match self.project {
+            Some(id) => Project::get_by_id(id, db).await?,
+            None => None,
+        };
+
+        Ok(project.map(|x| x.name).unwrap_or_default())
+    }
+
+    /// Returns whether the capsule must be approved before being published.
+    pub fn requires_approval(&self, config: &Config) -> bool {
+        config.approval_required || self.approval_required
+    }
+
+    /// Moves the capsule to the trash.
+    ///
+    /// The capsule is hidden from its project, but it stays attached to it and its data is kept
+    /// until it is restored or purged.
+    pub async fn trash(&mut self, db: &Db) -> Result<()> {
+        check_trash_state(self.deleted, false)?;
+
+        self.deleted = Some(Utc::now().naive_utc());
+        self.save(db).await?;
+        Ok(())
+    }
+
+    /// Restores the capsule from the trash, back in its project.
+    ///
+    /// If its project no longer exists, e.g. because it belonged to a deleted user, the capsule
+    /// gets a project of its owner named after it.
+    pub async fn restore(&mut self, db: &Db) -> Result<()> {
+        check_trash_state(self.deleted, true)?;
+
+        self.deleted = None;
+        self.save(db).await?;
+
+        let owner = self.owner(db).await?;
+        let project = match self.project {
+            Some(id) => Project::get_by_id(id, db).await?,
+            None => None,
+        };
+
+        let mut project = match project {
+            Some(project) if project.owner(db).await?.id == owner.id => project,
+            _ => Project::get_or_create(&self.name.clone(), &owner, db).await?,
+        };
+
+        project.add_capsule(self, db).await
+    }
+
+    /// Deletes the capsule and its data for good.
+    pub async fn purge(mut self, config: &Config, db: &Db) -> Result<()> {
+        let dir = config.data_path.join(format!("{}", self.id));
+        remove_dir_all(dir).await.ok();
+
+        // The tags and folders of the capsule are removed with it by the join tables.
+        Project::detach_capsule(&mut self, db).await?;
+        self.delete(db).await?;
+        Ok(())
+    }
+
+    /// Purges the capsules that have been in the trash for longer than the retention period.
+    ///
+    /// A capsule that cannot be purged is logged and left for the next sweep, so that it does not
+    /// block the others.
+    pub async fn purge_expired(config: &Config, db: &Db) -> Result<()> {
+        let limit = trash_limit(Utc::now().naive_utc(), config.trash_retention_days);
+
+        let capsules = Capsule::select()
+            .filter(capsule::deleted::leq(Some(limit)))
+            .execute(db)
+            .await?;
+
+        for capsule in capsules {
+            let id = capsule.id;
+            info!("Purging capsule {} from the trash", id);
+
+            if let Err(e) = capsule.purge(config, db).await {
+                error!("Failed to purge capsule {} from the trash: {:?}", id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the format of the video of the capsule.
+    pub fn output_settings(&self, config: &Config) -> OutputSettings {
+        match &self.output_settings {
+            Some(settings) => settings.0,
+            None => OutputSettings::from_config(config),
+        }
+    }
+
+    /// Returns the duration of the slides without record that do not specify one.
+    pub fn slide_duration_ms(&self) -> i32 {
+        self.slide_duration_ms.unwrap_or(DEFAULT_SLIDE_DURATION_MS)
+    }
+
+    /// Returns the structure of the capsule as given to production and publication, where every
+    /// slide has its duration.
+    pub fn production_structure(&self) -> Vec<Gos> {
+        let default = self.slide_duration_ms();
+        let mut structure = self.structure.0.clone();
+
+        for slide in structure.iter_mut().flat_map(|gos| gos.slides.iter_mut()) {
+            slide.duration_ms = Some(slide.duration_ms.unwrap_or(default));
+        }
+
+        structure
+    }
+
     /// Sets the last modified to now.
     pub fn set_changed(&mut self) {
         self.last_modified = Utc::now().naive_utc();
@@ -374,10 +809,24 @@ impl Capsule {
             })
             .collect::<Vec<_>>();
 
+        let pending_invitations = self
+            .pending_invitations(&db)
+            .await?
+            .into_iter()
+            .map(|x| {
+                json!({
+                    "email": x.email,
+                    "role": x.role,
+                    "expires": x.expires.timestamp(),
+                })
+            })
+            .collect::<Vec<_>>();
+
         Ok(json!({
             "id": HARSH.encode(self.id),
             "name": self.name,
-            "project": self.project,
+            "project": self.project_name(db).await?,
+            "project_id": self.project.map(|x| HARSH.encode(x)),
             "role": role,
             "video_uploaded": self.video_uploaded,
             "produced": self.produced,
@@ -386,9 +835,18 @@ impl Capsule {
             "structure": self.structure.0,
             "last_modified": self.last_modified.timestamp(),
             "users": users,
+            "pending_invitations": pending_invitations,
             "prompt_subtitles": self.prompt_subtitles,
             "disk_usage":self.disk_usage,
-            "duration_ms":self.duration_ms
+            "duration_ms":self.duration_ms,
+            "organization": self.organization,
+            "approval": self.approval,
+            "approval_required": self.approval_required,
+            "approval_comment": self.approval_comment,
+            "deleted": self.deleted.map(|x| x.timestamp()),
+            "output_settings": self.output_settings.as_ref().map(|x| x.0),
+            "slide_duration_ms": self.slide_duration_ms,
+            "audio_tracks": self.audio_tracks.0,
         }))
     }
 
@@ -507,3 +965,317 @@ impl Capsule {
         Err(Error(Status::NotFound))
     }
 }
+
+/// The number of capsules returned per page of a listing.
+pub const LISTING_PAGE_SIZE: i64 = 50;
+
+/// The different orders in which capsules can be listed.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CapsuleSort {
+    /// Sort by name.
+    Name,
+
+    /// Sort by last modification time.
+    LastModified,
+
+    /// Sort by duration of the produced video.
+    Duration,
+}
+
+impl CapsuleSort {
+    /// Returns the column the capsules are sorted by.
+    fn column(self) -> &'static str {
+        match self {
+            CapsuleSort::Name => "c.name",
+            CapsuleSort::LastModified => "c.last_modified",
+            CapsuleSort::Duration => "c.duration_ms",
+        }
+    }
+}
+
+/// A page of the capsules of a user, with filters and sorting.
+pub struct CapsuleListing {
+    /// Only list capsules from the project with this id.
+    pub project: Option<i32>,
+
+    /// Only list capsules on which the user has this role.
+    pub role: Option<Role>,
+
+    /// Only list capsules whose production is in this state.
+    pub produced: Option<TaskStatus>,
+
+    /// Only list capsules whose publication is in this state.
+    pub published: Option<TaskStatus>,
+
+    /// Only list capsules with this privacy.
+    pub privacy: Option<Privacy>,
+
+    /// Only list capsules on which the user put this tag.
+    pub tag: Option<i32>,
+
+    /// Only list capsules that the user put in this folder.
+    pub folder: Option<i32>,
+
+    /// The order of the capsules.
+    pub sort: CapsuleSort,
+
+    /// Whether the order is descending.
+    pub descending: bool,
+
+    /// The page to return, starting at 0.
+    pub page: i64,
+}
+
+impl CapsuleListing {
+    /// Returns the page of capsules of the user matching the listing, with their total count.
+    ///
+    /// Capsules are returned as summaries, without their structure nor their users, so that
+    /// listing them only takes one query.
+    pub async fn execute(&self, user: &User, db: &Db) -> Result<Value> {
+        // The enums are compared through their text representation, which is the same as their
+        // serde representation.
+        let text = |x: Value| x.as_str().map(String::from);
+
+        let query = format!(
+            "SELECT c.id, c.name, COALESCE(p.name, ''), j.role::text, c.produced::text,
+                c.published::text, c.privacy::text, c.last_modified, c.duration_ms, c.disk_usage,
+                c.organization, COUNT(*) OVER (), c.project
+            FROM capsules c
+            JOIN capsules_users_join j ON j.capsules_id = c.id
+            LEFT JOIN projects p ON p.id = c.project
+            WHERE j.users_id = $1
+                AND c.deleted IS NULL
+                AND ($2::int IS NULL OR c.project = $2)
+                AND ($3::text IS NULL OR j.role::text = $3)
+                AND ($4::text IS NULL OR c.produced::text = $4)
+                AND ($5::text IS NULL OR c.published::text = $5)
+                AND ($6::text IS NULL OR c.privacy::text = $6)
+                AND ($7::int IS NULL OR c.id IN (
+                    SELECT t.capsules_id FROM tags_capsules_join t
+                    JOIN tags ON tags.id = t.tags_id
+                    WHERE t.tags_id = $7 AND tags.owner = $1
+                ))
+                AND ($8::int IS NULL OR c.id IN (
+                    SELECT f.capsules_id FROM folders_capsules_join f
+                    JOIN folders ON folders.id = f.folders_id
+                    WHERE f.folders_id = $8 AND folders.owner = $1
+                ))
+            ORDER BY {column} {order}, c.id
+            LIMIT $9 OFFSET $10",
+            column = self.sort.column(),
+            order = if self.descending { "DESC" } else { "ASC" },
+        );
+
+        let rows = db
+            .client
+            .query(
+                query.as_str(),
+                &[
+                    &user.id as &(dyn ToSql + Sync),
+                    &self.project,
+                    &self.role.map(|x| json!(x)).and_then(text),
+                    &self.produced.map(|x| json!(x)).and_then(text),
+                    &self.published.map(|x| json!(x)).and_then(text),
+                    &self.privacy.as_ref().map(|x| json!(x)).and_then(text),
+                    &self.tag,
+                    &self.folder,
+                    &LISTING_PAGE_SIZE,
+                    &(self.page.max(0) * LISTING_PAGE_SIZE),
+                ],
+            )
+            .await?;
+
+        let total = rows.get(0).map(|x| x.get::<_, i64>(11)).unwrap_or(0);
+
+        let capsules = rows
+            .iter()
+            .map(|row| {
+                json!({
+                    "id": HARSH.encode(row.get::<_, i32>(0)),
+                    "name": row.get::<_, String>(1),
+                    "project": row.get::<_, String>(2),
+                    "role": row.get::<_, String>(3),
+                    "produced": row.get::<_, String>(4),
+                    "published": row.get::<_, String>(5),
+                    "privacy": row.get::<_, String>(6),
+                    "last_modified": row.get::<_, NaiveDateTime>(7).timestamp(),
+                    "duration_ms": row.get::<_, i32>(8),
+                    "disk_usage": row.get::<_, i32>(9),
+                    "organization": row.get::<_, Option<i32>>(10),
+                    "project_id": row.get::<_, Option<i32>>(12).map(|x| HARSH.encode(x)),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!({
+            "capsules": capsules,
+            "total": total,
+            "page": self.page.max(0),
+            "page_size": LISTING_PAGE_SIZE,
+        }))
+    }
+}
+
+/// Checks that a capsule is in the trash if `trashed` is true, and out of it otherwise.
+fn check_trash_state(deleted: Option<NaiveDateTime>, trashed: bool) -> Result<()> {
+    if deleted.is_some() != trashed {
+        return Err(Error(Status::Conflict));
+    }
+
+    Ok(())
+}
+
+/// Returns the date before which capsules moved to the trash must be purged.
+fn trash_limit(now: NaiveDateTime, retention_days: i64) -> NaiveDateTime {
+    now - Duration::days(retention_days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trash_state_rejects_trashing_twice_and_restoring_a_live_capsule() {
+        let now = Some(Utc::now().naive_utc());
+
+        assert!(check_trash_state(None, false).is_ok());
+        assert!(check_trash_state(now, true).is_ok());
+        assert_eq!(
+            check_trash_state(now, false).unwrap_err().0,
+            Status::Conflict
+        );
+        assert_eq!(
+            check_trash_state(None, true).unwrap_err().0,
+            Status::Conflict
+        );
+    }
+
+    #[test]
+    fn trash_limit_keeps_capsules_for_the_retention_period() {
+        let now = Utc::now().naive_utc();
+        let limit = trash_limit(now, 30);
+
+        assert_eq!(now - limit, Duration::days(30));
+        assert!(now - Duration::days(29) > limit);
+        assert!(now - Duration::days(31) <= limit);
+    }
+
+    /// Returns output settings with the given size and framerate.
+    fn settings(width: u32, height: u32, fps: u32) -> OutputSettings {
+        OutputSettings { width, height, fps }
+    }
+
+    #[test]
+    fn output_settings_accept_the_limits_of_the_plan() {
+        assert!(settings(1920, 1080, 30).check(Plan::Free).is_ok());
+        assert!(settings(240, 240, 1).check(Plan::Free).is_ok());
+        assert!(settings(3840, 2160, 60).check(Plan::PremiumLvl1).is_ok());
+    }
+
+    #[test]
+    fn output_settings_reject_sizes_out_of_the_plan() {
+        assert!(settings(3840, 2160, 30).check(Plan::Free).is_err());
+        assert!(settings(238, 240, 25).check(Plan::Admin).is_err());
+        assert!(settings(1281, 720, 25).check(Plan::Admin).is_err());
+    }
+
+    #[test]
+    fn output_settings_reject_framerates_out_of_the_plan() {
+        assert!(settings(1920, 1080, 0).check(Plan::Admin).is_err());
+        assert!(settings(1920, 1080, 60).check(Plan::Free).is_err());
+        assert!(settings(1920, 1080, 61).check(Plan::Admin).is_err());
+    }
+
+    /// Returns a gos with one slide lasting `duration_ms`.
+    fn gos_with_duration(duration_ms: Option<i32>) -> Gos {
+        let mut gos = Gos::from_slide(Uuid::nil(), String::new());
+        gos.slides[0].duration_ms = duration_ms;
+        gos
+    }
+
+    #[test]
+    fn slide_durations_accept_the_bounds() {
+        let structure = vec![
+            gos_with_duration(Some(MIN_SLIDE_DURATION_MS)),
+            gos_with_duration(Some(MAX_SLIDE_DURATION_MS)),
+            gos_with_duration(None),
+        ];
+
+        assert!(check_slide_durations(None, &structure).is_ok());
+        assert!(check_slide_durations(Some(DEFAULT_SLIDE_DURATION_MS), &structure).is_ok());
+    }
+
+    #[test]
+    fn slide_durations_reject_a_slide_out_of_bounds() {
+        let short = vec![gos_with_duration(Some(MIN_SLIDE_DURATION_MS - 1))];
+        let long = vec![gos_with_duration(Some(MAX_SLIDE_DURATION_MS + 1))];
+
+        assert!(check_slide_durations(None, &short).is_err());
+        assert!(check_slide_durations(None, &long).is_err());
+    }
+
+    #[test]
+    fn slide_durations_reject_a_default_out_of_bounds() {
+        assert!(check_slide_durations(Some(0), &[]).is_err());
+        assert!(check_slide_durations(Some(MAX_SLIDE_DURATION_MS + 1), &[]).is_err());
+    }
+
+    #[test]
+    fn transitions_accept_cuts_of_any_duration() {
+        assert!(Transition::cut().check().is_ok());
+
+        let cut = Transition {
+            ty: TransitionType::Cut,
+            duration_ms: -1,
+        };
+
+        assert!(cut.check().is_ok());
+    }
+
+    #[test]
+    fn transitions_check_their_duration() {
+        let transition = |duration_ms| Transition {
+            ty: TransitionType::Crossfade,
+            duration_ms,
+        };
+
+        assert!(transition(1).check().is_ok());
+        assert!(transition(MAX_TRANSITION_DURATION_MS).check().is_ok());
+        assert!(transition(0).check().is_err());
+        assert!(transition(MAX_TRANSITION_DURATION_MS + 1).check().is_err());
+    }
+
+    /// Returns an audio track playing from `start_gos` to `end_gos`.
+    fn track(start_gos: Option<i32>, end_gos: Option<i32>) -> AudioTrack {
+        AudioTrack {
+            start_gos,
+            end_gos,
+            ..AudioTrack::new(Uuid::nil(), String::from("music.mp3"))
+        }
+    }
+
+    #[test]
+    fn audio_tracks_check_their_volume() {
+        let volume = |volume| AudioTrack {
+            volume,
+            ..track(None, None)
+        };
+
+        assert!(volume(0.0).check().is_ok());
+        assert!(volume(MAX_AUDIO_TRACK_VOLUME).check().is_ok());
+        assert!(volume(-0.1).check().is_err());
+        assert!(volume(MAX_AUDIO_TRACK_VOLUME + 0.1).check().is_err());
+        assert!(volume(f32::NAN).check().is_err());
+    }
+
+    #[test]
+    fn audio_tracks_check_their_goss() {
+        assert!(track(None, None).check().is_ok());
+        assert!(track(Some(2), Some(2)).check().is_ok());
+        assert!(track(None, Some(0)).check().is_ok());
+        assert!(track(Some(-1), None).check().is_err());
+        assert!(track(None, Some(-1)).check().is_err());
+        assert!(track(Some(3), Some(2)).check().is_err());
+    }
+}
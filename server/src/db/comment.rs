@@ -0,0 +1,109 @@
+//! This module contains the comments left by reviewers on capsules.
+
+use chrono::{NaiveDateTime, Utc};
+
+use ergol::prelude::*;
+
+use tungstenite::Message;
+
+use rocket::serde::json::{json, Value};
+
+use crate::db::capsule::Capsule;
+use crate::db::user::User;
+use crate::websockets::WebSockets;
+use crate::{Db, Result, HARSH};
+
+/// A comment on a capsule, optionally attached to a gos, a slide, or a time of the record.
+#[ergol]
+pub struct Comment {
+    /// The id of the comment.
+    #[id]
+    pub id: i32,
+
+    /// The content of the comment.
+    pub content: String,
+
+    /// The index of the gos the comment refers to, if any.
+    pub gos: Option<i32>,
+
+    /// The uuid of the slide the comment refers to, if any.
+    pub slide: Option<String>,
+
+    /// The time of the record of the gos the comment refers to, in ms, if any.
+    pub time_ms: Option<i32>,
+
+    /// Whether the comment has been resolved or not.
+    pub resolved: bool,
+
+    /// The time when the comment was written.
+    pub created: NaiveDateTime,
+
+    /// The capsule that is commented.
+    #[many_to_one(comments)]
+    pub capsule: Capsule,
+
+    /// The user that wrote the comment.
+    #[many_to_one(comments)]
+    pub author: User,
+}
+
+impl Comment {
+    /// Creates and saves a new comment.
+    pub async fn new(
+        content: String,
+        gos: Option<i32>,
+        slide: Option<String>,
+        time_ms: Option<i32>,
+        capsule: &Capsule,
+        author: &User,
+        db: &Db,
+    ) -> Result<Comment> {
+        Ok(Comment::create(
+            content,
+            gos,
+            slide,
+            time_ms,
+            false,
+            Utc::now().naive_utc(),
+            capsule,
+            author,
+        )
+        .save(db)
+        .await?)
+    }
+
+    /// Returns a json representation of the comment.
+    pub async fn to_json(&self, db: &Db) -> Result<Value> {
+        Ok(json!({
+            "id": self.id,
+            "content": self.content,
+            "gos": self.gos,
+            "slide": self.slide,
+            "time_ms": self.time_ms,
+            "resolved": self.resolved,
+            "created": self.created.timestamp(),
+            "author": self.author(db).await?.username,
+        }))
+    }
+
+    /// Notifies the users of the capsule, except the one who triggered it, that the comment
+    /// changed.
+    pub async fn notify(&self, ty: &str, from: &User, db: &Db, sock: &WebSockets) -> Result<()> {
+        let capsule = self.capsule(db).await?;
+
+        let mut json = self.to_json(db).await?;
+        json["type"] = json!(ty);
+        json["capsule_id"] = json!(HARSH.encode(capsule.id));
+
+        for (user, _) in capsule.users(db).await? {
+            if user.id == from.id {
+                continue;
+            }
+
+            sock.write_message(user.id, Message::Text(json.to_string()))
+                .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+//! This module contains the exports of the data of users.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, NaiveDateTime, Utc};
+
+use ergol::prelude::*;
+
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
+
+use tokio::fs::{canonicalize, create_dir_all, remove_dir_all, remove_file, symlink, write};
+use tokio::process::Command;
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Value};
+
+use crate::config::Config;
+use crate::db::capsule::Role;
+use crate::db::task_status::TaskStatus;
+use crate::db::user::User;
+use crate::{Db, Error, Result, HARSH};
+
+/// An archive containing all the data of a user.
+#[ergol]
+pub struct DataExport {
+    /// The id of the export.
+    #[id]
+    pub id: i32,
+
+    /// The secret key used in the download link of the export.
+    #[unique]
+    pub key: String,
+
+    /// The state of the export.
+    pub status: TaskStatus,
+
+    /// The time when the export was requested.
+    pub created: NaiveDateTime,
+
+    /// The time after which the export can no longer be downloaded.
+    pub expires: NaiveDateTime,
+
+    /// The user whose data is exported.
+    #[many_to_one(data_exports)]
+    pub owner: User,
+}
+
+impl DataExport {
+    /// Creates a new export, removing the previous exports of the user.
+    pub async fn new(owner: &User, config: &Config, db: &Db) -> Result<DataExport> {
+        for export in owner.data_exports(db).await? {
+            if export.status == TaskStatus::Running {
+                return Err(Error(Status::Conflict));
+            }
+
+            export.remove(config, db).await?;
+        }
+
+        let rng = OsRng {};
+        let key = rng.sample_iter(&Alphanumeric).take(40).collect::<String>();
+        let now = Utc::now().naive_utc();
+
+        Ok(DataExport::create(
+            key,
+            TaskStatus::Running,
+            now,
+            now + Duration::hours(config.export_validity_hours),
+            owner,
+        )
+        .save(db)
+        .await?)
+    }
+
+    /// Returns the path of the archive of the export.
+    pub fn path(&self, config: &Config) -> PathBuf {
+        config.exports_path.join(format!("{}.tar.gz", self.key))
+    }
+
+    /// Returns whether the export can no longer be downloaded.
+    pub fn is_expired(&self) -> bool {
+        Utc::now().naive_utc() > self.expires
+    }
+
+    /// Deletes the export and its archive.
+    pub async fn remove(self, config: &Config, db: &Db) -> Result<()> {
+        remove_file(self.path(config)).await.ok();
+        self.delete(db).await?;
+        Ok(())
+    }
+
+    /// Marks as failed the exports that were interrupted by a restart of the server.
+    ///
+    /// Otherwise, they would stay running forever and their users could not request another one.
+    pub async fn fail_interrupted(config: &Config, db: &Db) -> Result<()> {
+        let exports = DataExport::select()
+            .filter(data_export::status::eq(TaskStatus::Running))
+            .execute(db)
+            .await?;
+
+        for mut export in exports {
+            info!("Marking interrupted export {} as failed", export.id);
+            remove_dir_all(config.exports_path.join(&export.key))
+                .await
+                .ok();
+            remove_file(export.path(config)).await.ok();
+            export.status = TaskStatus::Failed;
+            export.save(db).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the exports that can no longer be downloaded, along with their archives.
+    ///
+    /// A failure to delete an export is logged and does not prevent the others from being deleted.
+    pub async fn purge_expired(config: &Config, db: &Db) -> Result<()> {
+        let exports = DataExport::select()
+            .filter(data_export::expires::leq(Utc::now().naive_utc()))
+            .execute(db)
+            .await?;
+
+        for export in exports {
+            // The build of a running export would fail without its staging directory.
+            if export.status == TaskStatus::Running {
+                continue;
+            }
+
+            let id = export.id;
+            if let Err(e) = export.remove(config, db).await {
+                error!("Failed to delete expired export {}: {:?}", id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds the archive of the export.
+    ///
+    /// The archive contains the profile, the notifications and the sessions of the user, and for
+    /// each capsule they own, its structure, its assets and its produced video.
+    pub async fn build(&self, config: &Config, db: &Db) -> Result<()> {
+        let user = self.owner(db).await?;
+
+        let staging = config.exports_path.join(&self.key);
+        remove_dir_all(&staging).await.ok();
+        create_dir_all(&staging).await?;
+
+        let result = self.fill(&user, &staging, config, db).await;
+
+        let result = match result {
+            Ok(()) => {
+                let output = Command::new("tar")
+                    .arg("-czhf")
+                    .arg(self.path(config))
+                    .arg("-C")
+                    .arg(&staging)
+                    .arg(".")
+                    .output()
+                    .await?;
+
+                if output.status.success() {
+                    Ok(())
+                } else {
+                    error!(
+                        "Export failed:\n{}",
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                    Err(Error(Status::InternalServerError))
+                }
+            }
+            Err(e) => Err(e),
+        };
+
+        remove_dir_all(&staging).await.ok();
+        result
+    }
+
+    /// Writes the data of the user in the staging directory.
+    async fn fill(&self, user: &User, staging: &Path, config: &Config, db: &Db) -> Result<()> {
+        let profile = json!({
+            "username": user.username,
+            "email": user.email,
+            "secondary_email": user.secondary_email,
+            "activated": user.activated,
+            "subscribed": user.unsubscribe_key.is_some(),
+            "plan": user.plan,
+            "disk_quota": user.disk_quota,
+            "approver": user.approver,
+        });
+        write(staging.join("profile.json"), profile.to_string()).await?;
+
+        let notifications = user
+            .notifications(db)
+            .await?
+            .iter()
+            .map(|x| x.to_json())
+            .collect::<Vec<_>>();
+        write(
+            staging.join("notifications.json"),
+            json!(notifications).to_string(),
+        )
+        .await?;
+
+        // Only the metadata of the sessions is exported, never their secrets.
+        let sessions = user
+            .sessions(db)
+            .await?
+            .iter()
+            .map(|x| json!({ "id": x.id }))
+            .collect::<Vec<_>>();
+        write(staging.join("sessions.json"), json!(sessions).to_string()).await?;
+
+        let capsules_dir = staging.join("capsules");
+        create_dir_all(&capsules_dir).await?;
+
+        // The files are linked rather than copied, tar follows the links.
+        let data_path = canonicalize(&config.data_path).await?;
+
+        for (capsule, role) in user.capsules(db).await? {
+            if role != Role::Owner {
+                continue;
+            }
+
+            let dir = capsules_dir.join(HARSH.encode(capsule.id));
+            create_dir_all(&dir).await?;
+
+            write(
+                dir.join("capsule.json"),
+                capsule.to_json(Role::Owner, db).await?.to_string(),
+            )
+            .await?;
+
+            write(
+                dir.join("structure.json"),
+                json!(capsule.structure.0).to_string(),
+            )
+            .await?;
+
+            let data = data_path.join(format!("{}", capsule.id));
+
+            if data.join("assets").is_dir() {
+                symlink(data.join("assets"), dir.join("assets")).await?;
+            }
+
+            if data.join("output.mp4").is_file() {
+                symlink(data.join("output.mp4"), dir.join("output.mp4")).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns a json representation of the export.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "key": self.key,
+            "status": self.status,
+            "created": self.created.timestamp(),
+            "expires": self.expires.timestamp(),
+        })
+    }
+}
@@ -0,0 +1,151 @@
+//! This module contains the folders in which users organize their capsules.
+
+use ergol::prelude::*;
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Value};
+
+use crate::db::capsule::Capsule;
+use crate::db::user::User;
+use crate::{Db, Error, Result, HARSH};
+
+/// A folder of capsules, that can be nested in another folder.
+///
+/// Folders belong to a user, so that the collaborators of a shared capsule can put it in
+/// different folders. A capsule is in at most one folder of each user.
+#[ergol]
+pub struct Folder {
+    /// The id of the folder.
+    #[id]
+    pub id: i32,
+
+    /// The name of the folder.
+    pub name: String,
+
+    /// The id of the folder containing this folder, if any.
+    pub parent: Option<i32>,
+
+    /// The capsules in the folder.
+    #[many_to_many(folders)]
+    pub capsules: Capsule,
+
+    /// The user that owns the folder.
+    #[many_to_one(folders)]
+    pub owner: User,
+}
+
+impl Folder {
+    /// Creates a new empty folder.
+    pub async fn new<P: Into<String>>(
+        name: P,
+        parent: Option<i32>,
+        owner: &User,
+        db: &Db,
+    ) -> Result<Folder> {
+        let name = name.into();
+
+        if name.is_empty() {
+            return Err(Error(Status::BadRequest));
+        }
+
+        if let Some(parent) = parent {
+            Folder::get_owned(parent, owner, db).await?;
+        }
+
+        Ok(Folder::create(name, parent, owner).save(db).await?)
+    }
+
+    /// Gets a folder by id, checking that it belongs to the user.
+    pub async fn get_owned(id: i32, owner: &User, db: &Db) -> Result<Folder> {
+        let folder = Folder::get_by_id(id, db)
+            .await?
+            .ok_or(Error(Status::NotFound))?;
+
+        if folder.owner(db).await?.id != owner.id {
+            return Err(Error(Status::NotFound));
+        }
+
+        Ok(folder)
+    }
+
+    /// Moves the folder in another folder, or at the root if `parent` is `None`.
+    ///
+    /// A folder cannot be moved inside itself or one of its descendants.
+    pub async fn move_to(&mut self, parent: Option<i32>, db: &Db) -> Result<()> {
+        let owner = self.owner(db).await?;
+        let mut current = parent;
+
+        while let Some(id) = current {
+            if id == self.id {
+                return Err(Error(Status::BadRequest));
+            }
+
+            current = Folder::get_owned(id, &owner, db).await?.parent;
+        }
+
+        self.parent = parent;
+        self.save(db).await?;
+        Ok(())
+    }
+
+    /// Moves a capsule in a folder of a user, or at the root if `folder` is `None`.
+    pub async fn move_capsule(
+        capsule: &Capsule,
+        folder: Option<i32>,
+        user: &User,
+        db: &Db,
+    ) -> Result<()> {
+        let target = match folder {
+            Some(folder) => Some(Folder::get_owned(folder, user, db).await?),
+            None => None,
+        };
+
+        for folder in user.folders(db).await? {
+            folder.remove_capsule(capsule, db).await?;
+        }
+
+        if let Some(target) = target {
+            target.add_capsule(capsule, db).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the folder, moving its subfolders and capsules to its parent.
+    pub async fn remove(self, db: &Db) -> Result<()> {
+        let owner = self.owner(db).await?;
+
+        for mut folder in owner.folders(db).await? {
+            if folder.parent == Some(self.id) {
+                folder.parent = self.parent;
+                folder.save(db).await?;
+            }
+        }
+
+        if let Some(parent) = self.parent {
+            let parent = Folder::get_owned(parent, &owner, db).await?;
+
+            for capsule in self.capsules(db).await? {
+                parent.add_capsule(&capsule, db).await?;
+            }
+        }
+
+        self.delete(db).await?;
+        Ok(())
+    }
+
+    /// Returns a json representation of the folder.
+    pub async fn to_json(&self, db: &Db) -> Result<Value> {
+        Ok(json!({
+            "id": self.id,
+            "name": self.name,
+            "parent": self.parent,
+            "capsules": self
+                .capsules(db)
+                .await?
+                .iter()
+                .map(|x| HARSH.encode(x.id))
+                .collect::<Vec<_>>(),
+        }))
+    }
+}
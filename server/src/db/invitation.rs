@@ -0,0 +1,161 @@
+//! This module contains the pending invitations of people that don't have an account yet.
+
+use chrono::{Duration, NaiveDateTime, Utc};
+
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
+
+use ergol::prelude::*;
+
+use crate::config::Config;
+use crate::db::capsule::{Capsule, Role};
+use crate::db::user::User;
+use crate::templates::{capsule_invitation_html, capsule_invitation_plain_text};
+use crate::{Db, Result};
+
+/// An invitation to a capsule sent to an email address that has no account yet.
+#[ergol]
+pub struct PendingInvitation {
+    /// The id of the invitation.
+    #[id]
+    pub id: i32,
+
+    /// The email address of the invited person.
+    pub email: String,
+
+    /// The role the invited person will have once they have an account.
+    pub role: Role,
+
+    /// The key of the invitation, in the link sent to the invited person.
+    #[unique]
+    pub key: String,
+
+    /// The time after which the invitation can no longer be used.
+    pub expires: NaiveDateTime,
+
+    /// The capsule to which the person is invited.
+    #[many_to_one(pending_invitations)]
+    pub capsule: Capsule,
+}
+
+impl PendingInvitation {
+    /// Creates a pending invitation and sends an email to the invited person.
+    ///
+    /// If the email address was already invited to the capsule, the role and the validity of the
+    /// invitation are updated instead.
+    pub async fn new(
+        email: String,
+        role: Role,
+        capsule: &Capsule,
+        inviter: &User,
+        config: &Config,
+        db: &Db,
+    ) -> Result<PendingInvitation> {
+        let email = normalize_email(&email);
+        let expires = Utc::now().naive_utc() + Duration::days(config.invitation_validity_days);
+
+        let existing = capsule
+            .pending_invitations(db)
+            .await?
+            .into_iter()
+            .find(|x| x.email == email);
+
+        let invitation = match existing {
+            Some(mut invitation) => {
+                invitation.role = role;
+                invitation.expires = expires;
+                invitation.save(db).await?;
+                invitation
+            }
+            None => {
+                let key = OsRng {}
+                    .sample_iter(&Alphanumeric)
+                    .take(40)
+                    .collect::<String>();
+
+                PendingInvitation::create(email, role, key, expires, capsule)
+                    .save(db)
+                    .await?
+            }
+        };
+
+        if let Some(mailer) = &config.mailer {
+            let signup_url = format!("{}/capsule-invitation/{}", mailer.root, invitation.key);
+            let text = capsule_invitation_plain_text(&inviter.username, &capsule.name, &signup_url);
+            let html = capsule_invitation_html(&inviter.username, &capsule.name, &signup_url);
+
+            mailer.send_mail(
+                &invitation.email,
+                String::from("You have been invited to Polymny"),
+                text,
+                html,
+            )?;
+        }
+
+        Ok(invitation)
+    }
+
+    /// Returns the invitation with a key, if it has not expired.
+    pub async fn get_valid_by_key(key: String, db: &Db) -> Result<Option<PendingInvitation>> {
+        Ok(PendingInvitation::get_by_key(key, db)
+            .await?
+            .filter(|x| x.expires > Utc::now().naive_utc()))
+    }
+
+    /// Gives a user access to all the capsules to which their email address was invited.
+    ///
+    /// This must only be called once the user has proven that they own the email address. The
+    /// expired invitations are removed without giving access.
+    pub async fn attach_to(user: &User, db: &Db) -> Result<()> {
+        let invitations = PendingInvitation::select()
+            .filter(pending_invitation::email::eq(normalize_email(&user.email)))
+            .execute(db)
+            .await?;
+
+        for invitation in invitations {
+            if invitation.expires <= Utc::now().naive_utc() {
+                invitation.delete(db).await?;
+                continue;
+            }
+
+            let capsule = invitation.capsule(db).await?;
+
+            let already_in = capsule
+                .users(db)
+                .await?
+                .into_iter()
+                .any(|(x, _)| x.id == user.id);
+
+            if !already_in {
+                capsule.add_user(user, invitation.role, db).await?;
+            }
+
+            invitation.delete(db).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Normalizes an email address so that invitations match it whatever its case.
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_email_ignores_case_and_surrounding_spaces() {
+        assert_eq!(
+            normalize_email(" Jane.Doe@Example.COM "),
+            "jane.doe@example.com"
+        );
+        assert_eq!(
+            normalize_email("jane.doe@example.com"),
+            "jane.doe@example.com"
+        );
+    }
+}
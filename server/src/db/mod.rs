@@ -1,7 +1,17 @@
 //! This module contains everything that helps us deal with the library.
 
+pub mod approval;
+pub mod audit;
 pub mod capsule;
+pub mod comment;
+pub mod export;
+pub mod folder;
+pub mod invitation;
 pub mod notification;
+pub mod organization;
+pub mod project;
 pub mod session;
+pub mod tag;
 pub mod task_status;
+pub mod transfer;
 pub mod user;
@@ -0,0 +1,239 @@
+//! This module contains the organization struct and how it interacts with the database.
+
+use serde::{Deserialize, Serialize};
+
+use ergol::prelude::*;
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Value};
+
+use crate::db::capsule::{capsule, Capsule, Role};
+use crate::db::user::User;
+use crate::{Db, Error, Result, HARSH};
+
+/// The different roles a user can have in an organization.
+#[derive(Debug, Copy, Clone, PgEnum, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum OrganizationRole {
+    /// The user is a member of the organization, and can see its capsules.
+    Member,
+
+    /// The user administrates the organization, and can manage its members and capsules.
+    Admin,
+}
+
+/// An organization, grouping users and sharing the ownership of capsules.
+#[ergol]
+pub struct Organization {
+    /// The id of the organization.
+    #[id]
+    pub id: i32,
+
+    /// The name of the organization.
+    #[unique]
+    pub name: String,
+
+    /// The users that belong to the organization.
+    #[many_to_many(organizations, OrganizationRole)]
+    pub members: User,
+}
+
+impl Organization {
+    /// Creates a new organization, whose creator is its first admin.
+    pub async fn new<P: Into<String>>(name: P, creator: &User, db: &Db) -> Result<Organization> {
+        let name = name.into();
+
+        if name.is_empty() {
+            return Err(Error(Status::BadRequest));
+        }
+
+        if Organization::get_by_name(&name, db).await?.is_some() {
+            return Err(Error(Status::Conflict));
+        }
+
+        let organization = Organization::create(name).save(db).await?;
+        organization
+            .add_member(creator, OrganizationRole::Admin, db)
+            .await?;
+
+        Ok(organization)
+    }
+
+    /// Returns the role of a user in the organization, if they are a member.
+    pub async fn role_of(&self, user: &User, db: &Db) -> Result<Option<OrganizationRole>> {
+        Ok(self
+            .members(db)
+            .await?
+            .into_iter()
+            .find(|(member, _)| member.id == user.id)
+            .map(|(_, role)| role))
+    }
+
+    /// Returns whether a user is the only admin of the organization.
+    ///
+    /// The organization would be left without anyone to manage it if they left.
+    pub async fn is_last_admin(&self, user: &User, db: &Db) -> Result<bool> {
+        let members = self
+            .members(db)
+            .await?
+            .into_iter()
+            .map(|(member, role)| (member.id, role))
+            .collect::<Vec<_>>();
+
+        Ok(is_last_admin(&members, user.id))
+    }
+
+    /// Returns the role a member of the organization gets on the capsules of the organization.
+    pub fn capsule_role(role: OrganizationRole) -> Role {
+        match role {
+            OrganizationRole::Member => Role::Read,
+            OrganizationRole::Admin => Role::Owner,
+        }
+    }
+
+    /// Returns the capsules owned by the organization, including the ones in the trash.
+    pub async fn all_capsules(&self, db: &Db) -> Result<Vec<Capsule>> {
+        Ok(Capsule::select()
+            .filter(capsule::organization::eq(Some(self.id)))
+            .order_by(capsule::last_modified::descend())
+            .execute(db)
+            .await?)
+    }
+
+    /// Returns the capsules owned by the organization that are not in the trash.
+    pub async fn capsules(&self, db: &Db) -> Result<Vec<Capsule>> {
+        Ok(self
+            .all_capsules(db)
+            .await?
+            .into_iter()
+            .filter(|x| x.deleted.is_none())
+            .collect())
+    }
+
+    /// Returns the disk quota of the organization, pooled from the quotas of its members.
+    pub async fn disk_quota(&self, db: &Db) -> Result<i32> {
+        Ok(self
+            .members(db)
+            .await?
+            .iter()
+            .map(|(member, _)| member.disk_quota)
+            .sum())
+    }
+
+    /// Returns the disk usage of the capsules owned by the organization.
+    pub async fn disk_usage(&self, db: &Db) -> Result<i32> {
+        Ok(self
+            .capsules(db)
+            .await?
+            .iter()
+            .map(|capsule| capsule.disk_usage)
+            .sum())
+    }
+
+    /// Hands the organization capsules owned by a leaving user, including the ones in the trash,
+    /// to another admin of the organization.
+    ///
+    /// If the organization has no other admin, the capsules are left untouched.
+    pub async fn reassign_capsules(&self, leaving: &User, db: &Db) -> Result<()> {
+        let admin = self
+            .members(db)
+            .await?
+            .into_iter()
+            .find(|(member, role)| member.id != leaving.id && *role == OrganizationRole::Admin)
+            .map(|(member, _)| member);
+
+        let admin = match admin {
+            Some(admin) => admin,
+            None => return Ok(()),
+        };
+
+        for capsule in self.all_capsules(db).await? {
+            let users = capsule.users(db).await?;
+
+            let owned_by_leaving = users
+                .iter()
+                .any(|(user, role)| user.id == leaving.id && *role == Role::Owner);
+
+            if !owned_by_leaving {
+                continue;
+            }
+
+            if users.iter().any(|(user, _)| user.id == admin.id) {
+                capsule.update_role(&admin, Role::Owner, db).await?;
+            } else {
+                capsule.add_user(&admin, Role::Owner, db).await?;
+            }
+
+            capsule.remove_user(leaving, db).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns a json representation of the organization.
+    pub async fn to_json(&self, role: OrganizationRole, db: &Db) -> Result<Value> {
+        let members = self
+            .members(db)
+            .await?
+            .into_iter()
+            .map(|(member, role)| {
+                json!({
+                    "username": member.username,
+                    "role": role,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let capsules = self
+            .capsules(db)
+            .await?
+            .into_iter()
+            .map(|capsule| HARSH.encode(capsule.id))
+            .collect::<Vec<_>>();
+
+        Ok(json!({
+            "id": self.id,
+            "name": self.name,
+            "role": role,
+            "members": members,
+            "capsules": capsules,
+            "disk_quota": self.disk_quota(db).await?,
+            "disk_usage": self.disk_usage(db).await?,
+        }))
+    }
+}
+
+/// Returns whether a user is the only admin among the members of an organization, given by id.
+fn is_last_admin(members: &[(i32, OrganizationRole)], id: i32) -> bool {
+    let mut admins = members
+        .iter()
+        .filter(|(_, role)| *role == OrganizationRole::Admin)
+        .map(|(member, _)| *member);
+
+    admins.next() == Some(id) && admins.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use OrganizationRole::{Admin, Member};
+
+    #[test]
+    fn the_only_admin_is_the_last_admin() {
+        assert!(is_last_admin(&[(1, Admin), (2, Member)], 1));
+        assert!(is_last_admin(&[(1, Admin)], 1));
+    }
+
+    #[test]
+    fn an_admin_among_others_is_not_the_last_admin() {
+        assert!(!is_last_admin(&[(1, Admin), (2, Admin)], 1));
+        assert!(!is_last_admin(&[(2, Member), (1, Admin), (3, Admin)], 3));
+    }
+
+    #[test]
+    fn a_member_is_not_the_last_admin() {
+        assert!(!is_last_admin(&[(1, Admin), (2, Member)], 2));
+        assert!(!is_last_admin(&[], 1));
+    }
+}
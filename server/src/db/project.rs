@@ -0,0 +1,324 @@
+//! This module contains the project struct and how it interacts with the database.
+
+use ergol::prelude::*;
+use ergol::tokio_postgres::types::Json;
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Value};
+
+use crate::db::capsule::{capsule, Capsule, Role};
+use crate::db::user::User;
+use crate::{Db, Error, Result, HARSH};
+
+/// A project, that groups capsules of a user in a specific order.
+#[ergol]
+pub struct Project {
+    /// The id of the project.
+    #[id]
+    pub id: i32,
+
+    /// The name of the project.
+    pub name: String,
+
+    /// The description of the project.
+    pub description: String,
+
+    /// The ids of the capsules of the project, in the order they should be displayed.
+    ///
+    /// The capsules belong to the project through their `project` field, this is only used to
+    /// sort them: unknown ids are ignored and capsules missing from it come last.
+    pub capsule_order: Json<Vec<i32>>,
+
+    /// The user that owns the project.
+    #[many_to_one(projects)]
+    pub owner: User,
+}
+
+/// The sharing of a project with a user.
+#[ergol]
+pub struct ProjectShare {
+    /// The id of the share.
+    #[id]
+    pub id: i32,
+
+    /// The role of the user on the project.
+    pub role: Role,
+
+    /// The ids of the capsules on which the user got their role through the project.
+    ///
+    /// Only these roles are changed or revoked with the share, the roles that the user was given
+    /// directly on a capsule are left untouched.
+    pub granted: Json<Vec<i32>>,
+
+    /// The project that is shared.
+    #[many_to_one(shares)]
+    pub project: Project,
+
+    /// The user with whom the project is shared.
+    #[many_to_one(project_shares)]
+    pub member: User,
+}
+
+impl Project {
+    /// Creates a new empty project.
+    pub async fn new<P: Into<String>>(name: P, owner: &User, db: &Db) -> Result<Project> {
+        Ok(
+            Project::create(name.into(), String::new(), Json(vec![]), owner)
+                .save(db)
+                .await?,
+        )
+    }
+
+    /// Finds the project of a user from its name, creating it if it doesn't exist yet.
+    pub async fn get_or_create(name: &str, owner: &User, db: &Db) -> Result<Project> {
+        let project = owner
+            .projects(db)
+            .await?
+            .into_iter()
+            .find(|project| project.name == name);
+
+        match project {
+            Some(project) => Ok(project),
+            None => Project::new(name, owner, db).await,
+        }
+    }
+
+    /// Returns the capsules of the project, including the ones in the trash, in order.
+    pub async fn all_capsules(&self, db: &Db) -> Result<Vec<Capsule>> {
+        let mut capsules = Capsule::select()
+            .filter(capsule::project::eq(Some(self.id)))
+            .execute(db)
+            .await?;
+
+        let position = |id: i32| {
+            self.capsule_order
+                .0
+                .iter()
+                .position(|x| *x == id)
+                .unwrap_or(usize::MAX)
+        };
+
+        capsules.sort_by_key(|x| (position(x.id), x.id));
+        Ok(capsules)
+    }
+
+    /// Returns the capsules of the project that are not in the trash, in order.
+    pub async fn capsules(&self, db: &Db) -> Result<Vec<Capsule>> {
+        Ok(self
+            .all_capsules(db)
+            .await?
+            .into_iter()
+            .filter(|x| x.deleted.is_none())
+            .collect())
+    }
+
+    /// Returns the users with whom the project is shared, and their roles.
+    pub async fn users(&self, db: &Db) -> Result<Vec<(User, Role)>> {
+        let mut users = vec![];
+        for share in self.shares(db).await? {
+            users.push((share.member(db).await?, share.role));
+        }
+        Ok(users)
+    }
+
+    /// Adds a capsule at the end of the project and shares it with the users of the project.
+    pub async fn add_capsule(&mut self, capsule: &mut Capsule, db: &Db) -> Result<()> {
+        if !self.capsule_order.0.contains(&capsule.id) {
+            self.capsule_order.0.push(capsule.id);
+            self.save(db).await?;
+        }
+
+        if capsule.project != Some(self.id) {
+            capsule.project = Some(self.id);
+            capsule.save(db).await?;
+        }
+
+        let capsule_users = capsule.users(db).await?;
+
+        for mut share in self.shares(db).await? {
+            let member = share.member(db).await?;
+            if !capsule_users.iter().any(|(x, _)| x.id == member.id) {
+                capsule.add_user(&member, share.role, db).await?;
+                share.granted.0.push(capsule.id);
+                share.save(db).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes a capsule from the order of the project.
+    pub async fn remove_capsule(&mut self, id: i32, db: &Db) -> Result<()> {
+        self.capsule_order.0.retain(|x| *x != id);
+        self.save(db).await?;
+        Ok(())
+    }
+
+    /// Removes a capsule from its project, revoking the roles that were granted through it.
+    ///
+    /// The project is kept even if no capsule belongs to it anymore.
+    pub async fn detach_capsule(capsule: &mut Capsule, db: &Db) -> Result<()> {
+        let project = match capsule.project.take() {
+            Some(id) => Project::get_by_id(id, db).await?,
+            None => return Ok(()),
+        };
+
+        capsule.save(db).await?;
+
+        if let Some(mut project) = project {
+            project.remove_capsule(capsule.id, db).await?;
+
+            for mut share in project.shares(db).await? {
+                if share.granted.0.contains(&capsule.id) {
+                    capsule.remove_user(&share.member(db).await?, db).await?;
+                    share.granted.0.retain(|x| *x != capsule.id);
+                    share.save(db).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves a capsule to the project of its owner with the specified name.
+    ///
+    /// The capsule is left without project if the name is empty.
+    pub async fn move_capsule(capsule: &mut Capsule, name: &str, db: &Db) -> Result<()> {
+        Project::detach_capsule(capsule, db).await?;
+
+        if name.is_empty() {
+            return Ok(());
+        }
+
+        let owner = capsule.owner(db).await?;
+        let mut project = Project::get_or_create(name, &owner, db).await?;
+        project.add_capsule(capsule, db).await
+    }
+
+    /// Renames the project.
+    ///
+    /// Fails with a conflict if the owner already has another project with the same name.
+    pub async fn rename(&mut self, name: String, db: &Db) -> Result<()> {
+        if name.is_empty() {
+            return Err(Error(Status::BadRequest));
+        }
+
+        let owner = self.owner(db).await?;
+        if owner
+            .projects(db)
+            .await?
+            .iter()
+            .any(|x| x.id != self.id && x.name == name)
+        {
+            return Err(Error(Status::Conflict));
+        }
+
+        self.name = name;
+        self.save(db).await?;
+        Ok(())
+    }
+
+    /// Returns the share of the project with a user, if any.
+    pub async fn share_of(&self, user: &User, db: &Db) -> Result<Option<ProjectShare>> {
+        Ok(ProjectShare::select()
+            .filter(project_share::project::eq(self.id).and(project_share::member::eq(user.id)))
+            .execute(db)
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Shares the project and all its capsules with a user.
+    ///
+    /// The capsules on which the user already has a role of their own keep it.
+    pub async fn share(&self, user: &User, role: Role, db: &Db) -> Result<()> {
+        if role == Role::Owner {
+            return Err(Error(Status::BadRequest));
+        }
+
+        let mut share = match self.share_of(user, db).await? {
+            Some(mut share) => {
+                share.role = role;
+                share
+            }
+            None => {
+                ProjectShare::create(role, Json(vec![]), self, user)
+                    .save(db)
+                    .await?
+            }
+        };
+
+        for capsule in self.all_capsules(db).await? {
+            let current = capsule
+                .users(db)
+                .await?
+                .into_iter()
+                .find(|(x, _)| x.id == user.id)
+                .map(|(_, r)| r);
+
+            match current {
+                None => {
+                    capsule.add_user(user, role, db).await?;
+                    share.granted.0.push(capsule.id);
+                }
+                Some(_) if share.granted.0.contains(&capsule.id) => {
+                    capsule.update_role(user, role, db).await?;
+                }
+                Some(_) => (),
+            }
+        }
+
+        share.save(db).await?;
+
+        Ok(())
+    }
+
+    /// Stops sharing the project with a user, revoking the roles that were granted through it.
+    pub async fn unshare(&self, user: &User, db: &Db) -> Result<()> {
+        let share = match self.share_of(user, db).await? {
+            Some(share) => share,
+            None => return Ok(()),
+        };
+
+        for capsule in self.all_capsules(db).await? {
+            if share.granted.0.contains(&capsule.id) {
+                capsule.remove_user(user, db).await?;
+            }
+        }
+
+        share.delete(db).await?;
+
+        Ok(())
+    }
+
+    /// Returns a json representation of the project.
+    pub async fn to_json(&self, role: Role, db: &Db) -> Result<Value> {
+        let mut users = vec![json!({
+            "username": self.owner(db).await?.username,
+            "role": Role::Owner,
+        })];
+
+        for (user, role) in self.users(db).await? {
+            users.push(json!({
+                "username": user.username,
+                "role": role,
+            }));
+        }
+
+        let capsules = self
+            .capsules(db)
+            .await?
+            .iter()
+            .map(|x| HARSH.encode(x.id))
+            .collect::<Vec<_>>();
+
+        Ok(json!({
+            "id": HARSH.encode(self.id),
+            "name": self.name,
+            "description": self.description,
+            "role": role,
+            "capsules": capsules,
+            "users": users,
+        }))
+    }
+}
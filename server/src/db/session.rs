@@ -1,7 +1,16 @@
 //! This module contains the session struct and how it interacts with the database.
 
+use chrono::{Duration, NaiveDateTime, Utc};
+
 use ergol::prelude::*;
 
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
 use crate::db::user::User;
 use crate::{Db, Error};
 
@@ -16,6 +25,12 @@ pub struct Session {
     #[unique]
     pub secret: String,
 
+    /// The id of the admin viewing the website as the owner of the session, if any.
+    pub impersonator: Option<i32>,
+
+    /// The time after which the session is no longer valid, if any.
+    pub expires: Option<NaiveDateTime>,
+
     /// The user referenced by the session.
     #[many_to_one(sessions)]
     pub owner: User,
@@ -24,7 +39,154 @@ pub struct Session {
 impl Session {
     /// Creates and saves a session.
     pub async fn new(secret: String, owner: &User, db: &Db) -> Result<Session, Error> {
-        let session = Session::create(secret, owner).save(db).await?;
+        let session = Session::create(secret, None, None, owner).save(db).await?;
+        Ok(session)
+    }
+
+    /// Creates and saves a time-limited session allowing an admin to view the website as a user.
+    pub async fn impersonate(
+        admin: &User,
+        owner: &User,
+        minutes: i64,
+        db: &Db,
+    ) -> Result<Session, Error> {
+        let rng = OsRng {};
+        let secret = rng.sample_iter(&Alphanumeric).take(40).collect::<String>();
+        let expires = Utc::now().naive_utc() + Duration::minutes(minutes);
+
+        let session = Session::create(secret, Some(admin.id), Some(expires), owner)
+            .save(db)
+            .await?;
+
         Ok(session)
     }
+
+    /// Returns whether the session is no longer valid.
+    pub fn is_expired(&self) -> bool {
+        self.expires
+            .map(|x| Utc::now().naive_utc() > x)
+            .unwrap_or(false)
+    }
+
+    /// Gets the session of a request from its cookie.
+    pub async fn from_cookie(request: &Request<'_>, db: &Db) -> Result<Option<Session>, Error> {
+        let cookie = match request.cookies().get_private("EXAUTH") {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        Session::get_by_secret(cookie.value(), db)
+            .await
+            .map_err(Error::from)
+    }
+}
+
+/// The impersonation state of a request.
+///
+/// This guard never fails, it is empty if the request is not made through an impersonation
+/// session.
+pub struct Impersonation {
+    /// The admin impersonating the user.
+    pub admin: Option<User>,
+
+    /// The time when the impersonation session ends.
+    pub expires: Option<NaiveDateTime>,
+}
+
+impl Impersonation {
+    /// Returns an empty impersonation state.
+    pub fn none() -> Impersonation {
+        Impersonation {
+            admin: None,
+            expires: None,
+        }
+    }
+
+    /// Returns the user to whom the actions of the request are attributed in the audit log.
+    ///
+    /// This is the admin when the request is made through an impersonation session, so that the
+    /// log shows who really acted.
+    pub fn actor<'a>(&'a self, user: &'a User) -> &'a User {
+        self.admin.as_ref().unwrap_or(user)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Impersonation {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let db = match request.guard::<Db>().await {
+            Outcome::Success(db) => db,
+            _ => return Outcome::Success(Impersonation::none()),
+        };
+
+        let session = match Session::from_cookie(request, &db).await {
+            Ok(Some(session)) => session,
+            _ => return Outcome::Success(Impersonation::none()),
+        };
+
+        let admin = match session.impersonator {
+            Some(id) => User::get_by_id(id, &db).await.ok().flatten(),
+            None => None,
+        };
+
+        Outcome::Success(Impersonation {
+            admin,
+            expires: session.expires,
+        })
+    }
+}
+
+/// A guard that fails if the request is made through an impersonation session.
+///
+/// This is used on destructive routes that an admin viewing the website as a user must not
+/// trigger.
+pub struct NotImpersonated;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for NotImpersonated {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let db = match request.guard::<Db>().await {
+            Outcome::Success(db) => db,
+            Outcome::Failure(x) => return Outcome::Failure(x),
+            Outcome::Forward(()) => return Outcome::Forward(()),
+        };
+
+        let result = Session::from_cookie(request, &db)
+            .await
+            .and_then(|session| check_not_impersonated(session.and_then(|x| x.impersonator)));
+
+        match result {
+            Ok(()) => Outcome::Success(NotImpersonated),
+            Err(e) => Outcome::Failure((e.0, e)),
+        }
+    }
+}
+
+/// Checks that a request is not made through an impersonation session, given the id of the
+/// impersonator of its session, if any.
+fn check_not_impersonated(impersonator: Option<i32>) -> Result<(), Error> {
+    match impersonator {
+        Some(_) => Err(Error(Status::Forbidden)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn impersonation_sessions_are_forbidden() {
+        let result = check_not_impersonated(Some(1));
+        assert_eq!(result.err().map(|x| x.0), Some(Status::Forbidden));
+    }
+
+    #[test]
+    fn regular_sessions_are_allowed() {
+        assert!(check_not_impersonated(None).is_ok());
+    }
 }
@@ -0,0 +1,84 @@
+//! This module contains the tags that users put on capsules.
+
+use ergol::prelude::*;
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Value};
+
+use crate::db::capsule::Capsule;
+use crate::db::user::User;
+use crate::{Db, Error, Result, HARSH};
+
+/// A tag that a user puts on capsules to organize them.
+///
+/// Tags belong to a user, so that the collaborators of a shared capsule can tag it differently.
+#[ergol]
+pub struct Tag {
+    /// The id of the tag.
+    #[id]
+    pub id: i32,
+
+    /// The name of the tag.
+    pub name: String,
+
+    /// The capsules with this tag.
+    #[many_to_many(tags)]
+    pub capsules: Capsule,
+
+    /// The user that owns the tag.
+    #[many_to_one(tags)]
+    pub owner: User,
+}
+
+impl Tag {
+    /// Creates a new tag without capsules.
+    pub async fn new<P: Into<String>>(name: P, owner: &User, db: &Db) -> Result<Tag> {
+        let name = name.into();
+
+        if name.is_empty() {
+            return Err(Error(Status::BadRequest));
+        }
+
+        if owner.tags(db).await?.iter().any(|x| x.name == name) {
+            return Err(Error(Status::Conflict));
+        }
+
+        Ok(Tag::create(name, owner).save(db).await?)
+    }
+
+    /// Gets a tag by id, checking that it belongs to the user.
+    pub async fn get_owned(id: i32, owner: &User, db: &Db) -> Result<Tag> {
+        let tag = Tag::get_by_id(id, db)
+            .await?
+            .ok_or(Error(Status::NotFound))?;
+
+        if tag.owner(db).await?.id != owner.id {
+            return Err(Error(Status::NotFound));
+        }
+
+        Ok(tag)
+    }
+
+    /// Puts the tag on a capsule, unless it is already there.
+    pub async fn tag_capsule(&self, capsule: &Capsule, db: &Db) -> Result<()> {
+        if !self.capsules(db).await?.iter().any(|x| x.id == capsule.id) {
+            self.add_capsule(capsule, db).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns a json representation of the tag.
+    pub async fn to_json(&self, db: &Db) -> Result<Value> {
+        Ok(json!({
+            "id": self.id,
+            "name": self.name,
+            "capsules": self
+                .capsules(db)
+                .await?
+                .iter()
+                .map(|x| HARSH.encode(x.id))
+                .collect::<Vec<_>>(),
+        }))
+    }
+}
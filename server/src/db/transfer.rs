@@ -0,0 +1,112 @@
+//! This module contains the ownership transfer struct, representing a capsule being handed from a
+//! user to another.
+
+use ergol::prelude::*;
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Value};
+
+use crate::db::capsule::{Capsule, Role};
+use crate::db::project::Project;
+use crate::db::user::User;
+use crate::{Db, Error, Result, HARSH};
+
+/// A pending transfer of the ownership of a capsule, waiting for the recipient to accept it.
+#[ergol]
+pub struct OwnershipTransfer {
+    /// The id of the transfer.
+    #[id]
+    pub id: i32,
+
+    /// The capsule being transferred.
+    #[many_to_one(ownership_transfers)]
+    pub capsule: Capsule,
+
+    /// The current owner of the capsule.
+    #[many_to_one(outgoing_transfers)]
+    pub sender: User,
+
+    /// The user that will become the owner of the capsule.
+    #[many_to_one(incoming_transfers)]
+    pub recipient: User,
+}
+
+impl OwnershipTransfer {
+    /// Creates a new transfer, replacing any pending transfer of the same capsule.
+    pub async fn new(
+        capsule: &Capsule,
+        sender: &User,
+        recipient: &User,
+        db: &Db,
+    ) -> Result<OwnershipTransfer> {
+        if sender.id == recipient.id {
+            return Err(Error(Status::BadRequest));
+        }
+
+        for transfer in capsule.ownership_transfers(db).await? {
+            transfer.delete(db).await?;
+        }
+
+        Ok(OwnershipTransfer::create(capsule, sender, recipient)
+            .save(db)
+            .await?)
+    }
+
+    /// Accepts the transfer.
+    ///
+    /// The recipient becomes the owner of the capsule and the sender keeps a write access to it.
+    /// The transfer is refused if the capsule doesn't fit in the disk quota of the recipient.
+    pub async fn accept(self, db: &Db) -> Result<Capsule> {
+        let mut capsule = self.capsule(db).await?;
+        let sender = self.sender(db).await?;
+        let recipient = self.recipient(db).await?;
+
+        let users = capsule.users(db).await?;
+
+        if !users
+            .iter()
+            .any(|(user, role)| user.id == sender.id && *role == Role::Owner)
+        {
+            // The sender is not the owner anymore, the transfer is obsolete.
+            self.delete(db).await?;
+            return Err(Error(Status::Conflict));
+        }
+
+        let usage = recipient.disk_usage(db).await? as i64 + capsule.disk_usage as i64;
+        if usage > recipient.disk_quota_mb() {
+            return Err(Error(Status::InsufficientStorage));
+        }
+
+        let project = capsule.project_name(db).await?;
+        Project::detach_capsule(&mut capsule, db).await?;
+
+        if users.iter().any(|(user, _)| user.id == recipient.id) {
+            capsule.update_role(&recipient, Role::Owner, db).await?;
+        } else {
+            capsule.add_user(&recipient, Role::Owner, db).await?;
+        }
+
+        capsule.update_role(&sender, Role::Write, db).await?;
+
+        let mut project = Project::get_or_create(&project, &recipient, db).await?;
+        project.add_capsule(&mut capsule, db).await?;
+
+        self.delete(db).await?;
+
+        Ok(capsule)
+    }
+
+    /// Returns a json representation of the transfer.
+    pub async fn to_json(&self, db: &Db) -> Result<Value> {
+        let capsule = self.capsule(db).await?;
+
+        Ok(json!({
+            "id": self.id,
+            "capsule_id": HARSH.encode(capsule.id),
+            "capsule_name": capsule.name,
+            "project": capsule.project_name(db).await?,
+            "sender": self.sender(db).await?.username,
+            "recipient": self.recipient(db).await?.username,
+        }))
+    }
+}
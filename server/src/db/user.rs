@@ -1,10 +1,13 @@
 //! This module contains the user struct and how it interacts with the database.
 
+use chrono::{NaiveDateTime, Utc};
+
 use futures::future::try_join_all;
 
 use serde::{Deserialize, Serialize};
 
 use ergol::prelude::*;
+use ergol::tokio_postgres::types::ToSql;
 
 use rand::distributions::Alphanumeric;
 use rand::rngs::OsRng;
@@ -17,8 +20,13 @@ use rocket::request::{FromRequest, Outcome, Request};
 use rocket::serde::json::{json, Value};
 
 use crate::config::Config;
+use crate::db::approval::ApprovalStatus;
+use crate::db::audit::{AuditAction, AuditEvent};
 use crate::db::capsule::{capsule, Capsule, Role};
+use crate::db::invitation::PendingInvitation;
 use crate::db::notification::Notification;
+use crate::db::organization::Organization;
+use crate::db::project::{project, Project};
 use crate::db::session::Session;
 use crate::mailer::Mailer;
 use crate::templates::{
@@ -27,7 +35,7 @@ use crate::templates::{
     validation_new_email_html, validation_new_email_plain_text,
 };
 use crate::websockets::WebSockets;
-use crate::{Db, Error, Result};
+use crate::{Db, Error, Result, HARSH};
 
 const PAGE_SIZE: usize = 50;
 
@@ -45,6 +53,33 @@ pub enum Plan {
     Admin,
 }
 
+impl Plan {
+    /// Returns the default disk quota of the plan (in GB).
+    pub fn default_disk_quota(self, config: &Config) -> i32 {
+        match self {
+            Plan::Free => config.quota_disk_free,
+            Plan::PremiumLvl1 => config.quota_disk_premiumlvl1 as i32,
+            Plan::Admin => config.quota_disk_admin as i32,
+        }
+    }
+
+    /// Returns the maximum length (in pixels) of the longest side of the videos of the plan.
+    pub fn max_output_size(self) -> u32 {
+        match self {
+            Plan::Free => 1920,
+            Plan::PremiumLvl1 | Plan::Admin => 3840,
+        }
+    }
+
+    /// Returns the maximum framerate of the videos of the plan.
+    pub fn max_output_fps(self) -> u32 {
+        match self {
+            Plan::Free => 30,
+            Plan::PremiumLvl1 | Plan::Admin => 60,
+        }
+    }
+}
+
 /// A user of polymny.
 #[ergol]
 #[derive(Serialize)]
@@ -91,6 +126,13 @@ pub struct User {
 
     /// The disk quota of user
     pub disk_quota: i32,
+
+    /// Whether the user can approve capsules before their publication.
+    pub approver: bool,
+
+    /// The time when the user activated their account, if they did.
+    #[serde(skip)]
+    pub activated_on: Option<NaiveDateTime>,
 }
 
 impl User {
@@ -154,6 +196,8 @@ impl User {
                 unsubscribe_key,
                 Plan::Free,
                 config.quota_disk_free,
+                false,
+                None,
             )
         } else {
             User::create(
@@ -168,11 +212,14 @@ impl User {
                 unsubscribe_key,
                 Plan::Free,
                 config.quota_disk_free,
+                false,
+                Some(Utc::now().naive_utc()),
             )
         };
 
-        let user = user.save(&db).await?;
-        Ok(user)
+        // The pending invitations are only attached once the email address is verified, when the
+        // account is activated.
+        Ok(user.save(&db).await?)
     }
 
     /// Request to change a user's email address.
@@ -203,20 +250,23 @@ impl User {
         Ok(())
     }
 
-    /// Validates a user's new email.
-    pub async fn validate_change_email(key: String, db: &Db) -> Result<()> {
+    /// Validates a user's new email, returning the old one.
+    pub async fn validate_change_email(key: String, db: &Db) -> Result<(User, String)> {
         let mut user = match User::get_by_secondary_email_key(key, &db).await? {
             Some(u) => u,
             _ => return Err(Error(Status::NotFound)),
         };
 
+        let old_email = user.email.clone();
+
         if let Some(new_email) = user.secondary_email.as_ref() {
             user.email = new_email.clone();
             user.secondary_email_key = None;
         }
 
         user.save(&db).await?;
-        Ok(())
+        PendingInvitation::attach_to(&user, db).await?;
+        Ok((user, old_email))
     }
 
     /// Authenticates a user from its username and password.
@@ -280,12 +330,56 @@ impl User {
             None => return Ok(None),
             Some(s) => s,
         };
+
+        if session.is_expired() {
+            // An impersonation that expires is audited like one that is stopped.
+            if let Some(admin) = session.impersonator {
+                let admin = User::get_by_id(admin, db).await?;
+                let owner = session.owner(db).await?;
+
+                AuditEvent::log(
+                    AuditAction::AdminStopImpersonation,
+                    admin.as_ref(),
+                    Some(format!("user:{}", owner.username)),
+                    Some(String::from("expired")),
+                    None,
+                    db,
+                )
+                .await?;
+            }
+
+            session.delete(&db).await?;
+            return Ok(None);
+        }
+
         Ok(Some(session.owner(&db).await?))
     }
 
+    /// Returns the disk usage of the capsules owned by the user (in MB).
+    pub async fn disk_usage(&self, db: &Db) -> Result<i32> {
+        Ok(self
+            .capsules(&db)
+            .await?
+            .iter()
+            .filter(|(_, role)| *role == Role::Owner)
+            .map(|(capsule, _)| capsule.disk_usage)
+            .sum())
+    }
+
+    /// Returns the disk quota of the user (in MB, the unit of the disk usages).
+    pub fn disk_quota_mb(&self) -> i64 {
+        // Disk usages are computed in blocks of 1 MiB and quotas are given in GiB.
+        self.disk_quota as i64 * 1024
+    }
+
     /// Returns a json representation of the user.
     pub async fn to_json(&self, db: &Db) -> Result<Value> {
-        let capsules = self.capsules(&db).await?;
+        let (trash, capsules): (Vec<_>, Vec<_>) = self
+            .capsules(&db)
+            .await?
+            .into_iter()
+            .partition(|(capsule, _)| capsule.deleted.is_some());
+
         let capsules = capsules
             .iter()
             .map(|(capsule, role)| capsule.to_json(*role, db))
@@ -293,6 +387,14 @@ impl User {
 
         let capsules = try_join_all(capsules).await?;
 
+        let trash = trash
+            .iter()
+            .filter(|(_, role)| *role == Role::Owner)
+            .map(|(capsule, role)| capsule.to_json(*role, db))
+            .collect::<Vec<_>>();
+
+        let trash = try_join_all(trash).await?;
+
         let notifications = self
             .notifications(&db)
             .await?
@@ -300,14 +402,19 @@ impl User {
             .map(|x| x.to_json())
             .collect::<Vec<_>>();
 
+        // The projects, organizations, tags, folders and transfers of the user have their own
+        // routes, so that they are only loaded when needed.
         Ok(json!({
             "username": self.username,
             "email": self.email,
             "cookie": self.sessions(&db).await?.get(0).map(|x| x.secret.clone()),
             "capsules": capsules,
+            "trash": trash,
             "notifications": notifications,
             "plan": self.plan,
-            "disk_quota": self.disk_quota
+            "disk_quota": self.disk_quota,
+            "disk_usage": self.disk_usage(&db).await?,
+            "approver": self.approver,
         }))
     }
 
@@ -321,6 +428,29 @@ impl User {
         Ok(user_json)
     }
 
+    /// Sends the activation email of the user again, with a new activation key.
+    pub async fn resend_activation(&mut self, mailer: &Option<Mailer>, db: &Db) -> Result<()> {
+        if self.activated {
+            return Err(Error(Status::Conflict));
+        }
+
+        let mailer = mailer.as_ref().ok_or(Error(Status::ServiceUnavailable))?;
+
+        let rng = OsRng {};
+        let activation_key = rng.sample_iter(&Alphanumeric).take(40).collect::<String>();
+
+        let activation_url = format!("{}/activate/{}", mailer.root, activation_key);
+        let text = validation_email_plain_text(&activation_url);
+        let html = validation_email_html(&activation_url);
+
+        mailer.send_mail(&self.email, String::from("Welcome to Polymny"), text, html)?;
+
+        self.activation_key = Some(activation_key);
+        self.save(&db).await?;
+
+        Ok(())
+    }
+
     /// Requests the user to change its password.
     pub async fn request_change_password(
         &mut self,
@@ -360,27 +490,194 @@ impl User {
         permission: Role,
         db: &Db,
     ) -> Result<(Capsule, Role)> {
-        if self.plan == Plan::Admin {
-            let capsule = Capsule::get_by_id(id, &db).await?;
-            if let Some(capsule) = capsule {
-                Ok((capsule, Role::Owner))
-            } else {
-                Err(Error(Status::NotFound))
-            }
+        let (capsule, role) = if self.plan == Plan::Admin {
+            let capsule = Capsule::get_by_id(id, &db)
+                .await?
+                .ok_or(Error(Status::NotFound))?;
+
+            (capsule, Role::Owner)
         } else {
-            Ok(self
+            let direct = self
                 .capsules(&db)
                 .await?
                 .into_iter()
-                .filter(|(x, r)| x.id == id && *r >= permission)
-                .nth(0)
-                .ok_or(Error(Status::NotFound))?)
+                .filter(|(x, _)| x.id == id)
+                .nth(0);
+
+            let (capsule, direct) = match direct {
+                Some((capsule, role)) => (capsule, Some(role)),
+                None => {
+                    let capsule = Capsule::get_by_id(id, &db)
+                        .await?
+                        .ok_or(Error(Status::NotFound))?;
+                    (capsule, None)
+                }
+            };
+
+            let organization = self.organization_role_for(&capsule, db).await?;
+            let reviewing = self.approver && capsule.approval == ApprovalStatus::Pending;
+
+            let role = match effective_role(direct, organization, reviewing) {
+                Some(role) if role >= permission => role,
+                _ => return Err(Error(Status::NotFound)),
+            };
+
+            (capsule, role)
+        };
+
+        // Capsules in the trash can only be restored or purged.
+        if capsule.deleted.is_some() {
+            return Err(Error(Status::NotFound));
         }
+
+        Ok((capsule, role))
+    }
+
+    /// Gets a capsule from the trash, checking that the user owns it.
+    pub async fn get_trashed_capsule(&self, id: i32, db: &Db) -> Result<Capsule> {
+        let capsule = Capsule::get_by_id(id, &db)
+            .await?
+            .ok_or(Error(Status::NotFound))?;
+
+        let allowed = self.plan == Plan::Admin
+            || (capsule.deleted.is_some() && capsule.owner(&db).await?.id == self.id);
+
+        check_trashed_access(capsule.deleted.is_some(), allowed)?;
+        Ok(capsule)
+    }
+
+    /// Searches the capsules of the user by name, project and prompts.
+    ///
+    /// The search uses the full-text search of PostgreSQL, with the text search configuration
+    /// matching the language of the user. Each capsule is returned with the slide whose prompt
+    /// matched best, if any.
+    pub async fn search_capsules(&self, search: &str, lang: &str, db: &Db) -> Result<Value> {
+        // The configuration is written in the query, it must not come from user input.
+        let config = match lang.split('-').next() {
+            Some("fr") => "french",
+            Some("en") => "english",
+            _ => "simple",
+        };
+
+        let query = format!(
+            "SELECT c.id, c.name, COALESCE(p.name, ''), j.role::text, c.last_modified,
+                m.gos, m.slide, m.uuid, m.headline,
+                GREATEST(
+                    ts_rank(to_tsvector('{config}', c.name || ' ' || COALESCE(p.name, '')), q),
+                    COALESCE(m.rank, 0)
+                ) AS rank
+            FROM capsules c
+            JOIN capsules_users_join j ON j.capsules_id = c.id
+            LEFT JOIN projects p ON p.id = c.project
+            CROSS JOIN websearch_to_tsquery('{config}', $2) q
+            LEFT JOIN LATERAL (
+                SELECT (g.idx - 1)::int AS gos, (s.idx - 1)::int AS slide,
+                    s.value->>'uuid' AS uuid,
+                    ts_headline('{config}', s.value->>'prompt', q) AS headline,
+                    ts_rank(to_tsvector('{config}', s.value->>'prompt'), q) AS rank
+                FROM json_array_elements(c.structure) WITH ORDINALITY g(value, idx),
+                    json_array_elements(g.value->'slides') WITH ORDINALITY s(value, idx)
+                WHERE to_tsvector('{config}', s.value->>'prompt') @@ q
+                ORDER BY rank DESC
+                LIMIT 1
+            ) m ON TRUE
+            WHERE j.users_id = $1
+                AND c.deleted IS NULL
+                AND (
+                    to_tsvector('{config}', c.name || ' ' || COALESCE(p.name, '')) @@ q
+                    OR m.slide IS NOT NULL
+                )
+            ORDER BY rank DESC, c.last_modified DESC
+            LIMIT $3",
+            config = config
+        );
+
+        let capsules = db
+            .client
+            .query(
+                query.as_str(),
+                &[
+                    &self.id as &(dyn ToSql + Sync),
+                    &search,
+                    &(PAGE_SIZE as i64),
+                ],
+            )
+            .await?
+            .into_iter()
+            .map(|row| {
+                let slide = row.get::<_, Option<i32>>(6).map(|slide| {
+                    json!({
+                        "gos": row.get::<_, i32>(5),
+                        "slide": slide,
+                        "uuid": row.get::<_, String>(7),
+                        "headline": row.get::<_, String>(8),
+                    })
+                });
+
+                json!({
+                    "id": HARSH.encode(row.get::<_, i32>(0)),
+                    "name": row.get::<_, String>(1),
+                    "project": row.get::<_, String>(2),
+                    "role": row.get::<_, String>(3),
+                    "last_modified": row.get::<_, NaiveDateTime>(4).timestamp(),
+                    "match": slide,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!(capsules))
+    }
+
+    /// Gets a project by id checking if the user have the sufficient permissions.
+    pub async fn get_project_with_permission(
+        &self,
+        id: i32,
+        permission: Role,
+        db: &Db,
+    ) -> Result<(Project, Role)> {
+        let project = Project::get_by_id(id, &db)
+            .await?
+            .ok_or(Error(Status::NotFound))?;
+
+        if self.plan == Plan::Admin || project.owner(&db).await?.id == self.id {
+            return Ok((project, Role::Owner));
+        }
+
+        let role = project
+            .users(&db)
+            .await?
+            .into_iter()
+            .filter(|(x, _)| x.id == self.id)
+            .map(|(_, r)| r)
+            .nth(0)
+            .ok_or(Error(Status::NotFound))?;
+
+        if role >= permission {
+            Ok((project, role))
+        } else {
+            Err(Error(Status::NotFound))
+        }
+    }
+
+    /// Returns the role the user gets on a capsule through the organization owning it, if any.
+    pub async fn organization_role_for(&self, capsule: &Capsule, db: &Db) -> Result<Option<Role>> {
+        let organization = match capsule.organization {
+            Some(id) => Organization::get_by_id(id, &db).await?,
+            None => None,
+        };
+
+        Ok(match organization {
+            Some(organization) => organization
+                .role_of(self, db)
+                .await?
+                .map(Organization::capsule_role),
+            None => None,
+        })
     }
 
     /// Invite a user to join polymny
     pub async fn request_invitation(
-        self,
+        &self,
         username: String,
         email: String,
         mailer: &Option<Mailer>,
@@ -428,6 +725,8 @@ impl User {
                             unsubscribe_key,
                             Plan::Free,
                             config.quota_disk_free,
+                            false,
+                            None,
                         )
                         .save(&db)
                         .await?;
@@ -499,6 +798,68 @@ impl<'r> FromRequest<'r> for User {
     }
 }
 
+/// Returns the role of a user on a capsule, from the role they were given on it and the role they
+/// get through the organization owning it, if any.
+///
+/// The highest of both roles wins. Approvers who have no role on a capsule can read it while
+/// they are reviewing it.
+fn effective_role(
+    direct: Option<Role>,
+    organization: Option<Role>,
+    reviewing: bool,
+) -> Option<Role> {
+    match (direct, organization) {
+        (Some(direct), Some(organization)) if organization > direct => Some(organization),
+        (Some(direct), _) => Some(direct),
+        (None, Some(organization)) => Some(organization),
+        (None, None) if reviewing => Some(Role::Read),
+        (None, None) => None,
+    }
+}
+
+/// Escapes the wildcards of a string so that it is matched literally by a `LIKE` pattern.
+fn escape_like(search: &str) -> String {
+    search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Counts the rows of a table per period of a timestamp column, over the last `count` calendar
+/// periods, the current one included.
+///
+/// The table and column must not come from user input, they are written in the query.
+async fn histogram(
+    table: &str,
+    column: &str,
+    period: &str,
+    count: i32,
+    db: &Db,
+) -> Result<Vec<Value>> {
+    let query = format!(
+        "SELECT date_trunc($1, {column}) AS period, COUNT(*)
+        FROM {table}
+        WHERE {column} >= date_trunc($1, now()::timestamp) - ($2::int - 1) * ('1 ' || $1)::interval
+        GROUP BY period
+        ORDER BY period",
+        table = table,
+        column = column
+    );
+
+    Ok(db
+        .client
+        .query(query.as_str(), &[&period as &(dyn ToSql + Sync), &count])
+        .await?
+        .into_iter()
+        .map(|row| {
+            json!({
+                "period": row.get::<_, NaiveDateTime>(0).timestamp(),
+                "count": row.get::<_, i64>(1),
+            })
+        })
+        .collect())
+}
+
 /// An administrator user.
 ///
 /// This is just a wrapper for a user that has admin rights.
@@ -552,9 +913,88 @@ impl Admin {
                    "capsules": capsules}))
     }
 
-    /// Dummy stats functions
-    pub async fn do_stats(&self, _db: &Db) -> Result<Value> {
-        Ok(json!({ "stats": "some stats"}))
+    /// Computes the statistics of the instance.
+    ///
+    /// The histograms count events per `period`, which must be `day`, `week` or `month`, over the
+    /// last `count` calendar periods.
+    pub async fn do_stats(&self, period: &str, count: i32, db: &Db) -> Result<Value> {
+        if !matches!(period, "day" | "week" | "month") {
+            return Err(Error(Status::BadRequest));
+        }
+
+        let count = count.max(1);
+
+        let mut plans = json!({});
+        for row in db
+            .client
+            .query("SELECT plan::text, COUNT(*) FROM users GROUP BY plan", &[])
+            .await?
+        {
+            plans[row.get::<_, String>(0)] = json!(row.get::<_, i64>(1));
+        }
+
+        let activations = histogram("users", "activated_on", period, count, db).await?;
+        let created = histogram("capsules", "created", period, count, db).await?;
+        let produced = histogram("capsules", "produced_on", period, count, db).await?;
+        let published = histogram("capsules", "published_on", period, count, db).await?;
+
+        let totals = db
+            .client
+            .query_one(
+                "SELECT
+                    COUNT(*),
+                    COALESCE(SUM(disk_usage), 0),
+                    COALESCE(SUM(duration_ms) FILTER (WHERE produced = 'done'), 0),
+                    COUNT(*) FILTER (WHERE video_uploaded = 'running'),
+                    COUNT(*) FILTER (WHERE produced = 'running'),
+                    COUNT(*) FILTER (WHERE published = 'running')
+                FROM capsules
+                WHERE deleted IS NULL",
+                &[],
+            )
+            .await?;
+
+        let disk_usage_per_user = db
+            .client
+            .query(
+                "SELECT users.username, COALESCE(SUM(capsules.disk_usage), 0), users.disk_quota
+                FROM users
+                JOIN capsules_users_join ON capsules_users_join.users_id = users.id
+                JOIN capsules ON capsules.id = capsules_users_join.capsules_id
+                WHERE capsules_users_join.role = 'owner' AND capsules.deleted IS NULL
+                GROUP BY users.id
+                ORDER BY 2 DESC
+                LIMIT 20",
+                &[],
+            )
+            .await?
+            .into_iter()
+            .map(|row| {
+                json!({
+                    "username": row.get::<_, String>(0),
+                    "disk_usage": row.get::<_, i64>(1),
+                    "disk_quota": row.get::<_, i32>(2),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!({
+            "period": period,
+            "users_per_plan": plans,
+            "activations": activations,
+            "capsules_created": created,
+            "capsules_produced": produced,
+            "capsules_published": published,
+            "capsules": totals.get::<_, i64>(0),
+            "disk_usage": totals.get::<_, i64>(1),
+            "disk_usage_per_user": disk_usage_per_user,
+            "video_minutes": totals.get::<_, i64>(2) as f64 / 60000.,
+            "running_tasks": {
+                "video_upload": totals.get::<_, i64>(3),
+                "production": totals.get::<_, i64>(4),
+                "publication": totals.get::<_, i64>(5),
+            },
+        }))
     }
 
     /// Returns a paged representation users.
@@ -580,7 +1020,7 @@ impl Admin {
     pub async fn search_by_username(&self, db: &Db, search: &str) -> Result<Value> {
         let users = futures::future::join_all(
             User::select()
-                .filter(user::username::like(format!("%{}%", search.to_string())))
+                .filter(user::username::like(format!("%{}%", escape_like(search))))
                 .order_by(user::id::descend())
                 .execute(&db)
                 .await?
@@ -598,7 +1038,7 @@ impl Admin {
     pub async fn search_by_email(&self, db: &Db, search: &str) -> Result<Value> {
         let users = futures::future::join_all(
             User::select()
-                .filter(user::email::like(format!("%{}%", search.to_string())))
+                .filter(user::email::like(format!("%{}%", escape_like(search))))
                 .order_by(user::id::descend())
                 .execute(&db)
                 .await?
@@ -646,7 +1086,7 @@ impl Admin {
     pub async fn search_by_capsule(&self, db: &Db, search: &str) -> Result<Value> {
         let capsules = futures::future::join_all(
             Capsule::select()
-                .filter(capsule::name::like(format!("%{}%", search.to_string())))
+                .filter(capsule::name::like(format!("%{}%", escape_like(search))))
                 .order_by(capsule::last_modified::descend())
                 .execute(&db)
                 .await?
@@ -662,12 +1102,20 @@ impl Admin {
 
     /// Search by project.
     pub async fn search_by_project(&self, db: &Db, search: &str) -> Result<Value> {
+        let projects = Project::select()
+            .filter(project::name::like(format!("%{}%", escape_like(search))))
+            .execute(&db)
+            .await?;
+
+        let mut capsules = vec![];
+        for project in projects {
+            capsules.extend(project.capsules(db).await?);
+        }
+
+        capsules.sort_by(|a, b| b.last_modified.cmp(&a.last_modified));
+
         let capsules = futures::future::join_all(
-            Capsule::select()
-                .filter(capsule::project::like(format!("%{}%", search.to_string())))
-                .order_by(capsule::last_modified::descend())
-                .execute(&db)
-                .await?
+            capsules
                 .iter()
                 .map(|capsule| capsule.to_json(Role::Read, db)),
         )
@@ -678,3 +1126,69 @@ impl Admin {
         Ok(json!(capsules))
     }
 }
+
+/// Checks that a capsule can be reached from the trash.
+///
+/// Only capsules in the trash can be reached, and only by their owner or an admin; anything else
+/// is reported as not found so that the trash does not leak the existence of capsules.
+fn check_trashed_access(trashed: bool, allowed: bool) -> Result<()> {
+    if !trashed || !allowed {
+        return Err(Error(Status::NotFound));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trashed_capsules_are_reachable_by_their_owner_or_an_admin() {
+        assert!(check_trashed_access(true, true).is_ok());
+    }
+
+    #[test]
+    fn trash_hides_other_capsules() {
+        for (trashed, allowed) in [(true, false), (false, true), (false, false)] {
+            let err = check_trashed_access(trashed, allowed).unwrap_err();
+            assert_eq!(err.0, Status::NotFound);
+        }
+    }
+
+    #[test]
+    fn effective_role_keeps_the_highest_role() {
+        let owner = Some(Role::Owner);
+        assert_eq!(effective_role(Some(Role::Read), owner, false), owner);
+        assert_eq!(effective_role(owner, Some(Role::Read), false), owner);
+        assert_eq!(
+            effective_role(Some(Role::Write), None, false),
+            Some(Role::Write)
+        );
+        assert_eq!(
+            effective_role(None, Some(Role::Read), false),
+            Some(Role::Read)
+        );
+    }
+
+    #[test]
+    fn effective_role_lets_approvers_read_the_capsules_they_review() {
+        assert_eq!(effective_role(None, None, true), Some(Role::Read));
+        assert_eq!(effective_role(None, None, false), None);
+        assert_eq!(
+            effective_role(Some(Role::Write), None, true),
+            Some(Role::Write)
+        );
+    }
+
+    #[test]
+    fn escape_like_escapes_wildcards() {
+        assert_eq!(escape_like("50%_off"), "50\\%\\_off");
+    }
+
+    #[test]
+    fn escape_like_escapes_the_escape_character_first() {
+        assert_eq!(escape_like("a\\%"), "a\\\\\\%");
+        assert_eq!(escape_like("plain"), "plain");
+    }
+}
@@ -374,6 +374,67 @@ pub async fn update_video_duration() {
     }
 }
 
+/// Periodically purges the capsules that have been in the trash for too long.
+async fn sweep_trash(config: Config, pool: Pool) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+
+    loop {
+        interval.tick().await;
+
+        let db = match Db::from_pool(pool.clone()).await {
+            Ok(db) => db,
+            Err(_) => {
+                error!("Cannot get a database connection to sweep the trash");
+                continue;
+            }
+        };
+
+        if db::capsule::Capsule::purge_expired(&config, &db)
+            .await
+            .is_err()
+        {
+            error!("Failed to sweep the trash");
+        }
+    }
+}
+
+/// Fails the exports interrupted by a restart, then periodically deletes the expired exports.
+async fn sweep_exports(config: Config, pool: Pool) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+    let mut started = false;
+
+    loop {
+        interval.tick().await;
+
+        let db = match Db::from_pool(pool.clone()).await {
+            Ok(db) => db,
+            Err(_) => {
+                error!("Cannot get a database connection to sweep the exports");
+                continue;
+            }
+        };
+
+        if !started {
+            if db::export::DataExport::fail_interrupted(&config, &db)
+                .await
+                .is_err()
+            {
+                error!("Failed to reset the interrupted exports");
+                continue;
+            }
+
+            started = true;
+        }
+
+        if db::export::DataExport::purge_expired(&config, &db)
+            .await
+            .is_err()
+        {
+            error!("Failed to sweep the exports");
+        }
+    }
+}
+
 /// Starts the rocket server.
 pub async fn rocket() -> StdResult<Rocket<Ignite>, rocket::Error> {
     let figment = rocket::Config::figment();
@@ -442,6 +503,7 @@ pub async fn rocket() -> StdResult<Rocket<Ignite>, rocket::Error> {
                 routes::admin_capsules,
                 routes::capsule_settings,
                 routes::user::activate,
+                routes::user::capsule_invitation,
                 routes::user::unsubscribe,
                 routes::user::reset_password,
                 routes::user::validate_email,
@@ -475,14 +537,27 @@ pub async fn rocket() -> StdResult<Rocket<Ignite>, rocket::Error> {
                 routes::user::change_password,
                 routes::user::request_change_email,
                 routes::user::request_invitation,
+                routes::user::request_export,
+                routes::user::get_export,
+                routes::user::stop_impersonation,
                 routes::capsule::get_capsule,
+                routes::capsule::get_capsules,
+                routes::capsule::search_capsules,
                 routes::capsule::empty_capsule,
                 routes::capsule::new_capsule,
+                routes::capsule::new_capsule_from_images,
+                routes::capsule::new_capsule_from_markdown,
+                routes::capsule::new_capsule_from_video,
                 routes::capsule::edit_capsule,
+                routes::capsule::set_output_settings,
                 routes::capsule::delete_capsule,
                 routes::capsule::delete_project,
+                routes::capsule::restore_capsule,
+                routes::capsule::purge_capsule,
                 routes::capsule::upload_record,
                 routes::capsule::upload_pointer,
+                routes::capsule::upload_audio,
+                routes::capsule::set_audio_tracks,
                 routes::capsule::replace_slide,
                 routes::capsule::add_slide,
                 routes::capsule::add_gos,
@@ -496,8 +571,45 @@ pub async fn rocket() -> StdResult<Rocket<Ignite>, rocket::Error> {
                 routes::capsule::invite,
                 routes::capsule::deinvite,
                 routes::capsule::change_role,
+                routes::capsule::incoming_transfers,
+                routes::capsule::transfer_ownership,
+                routes::capsule::accept_transfer,
+                routes::capsule::cancel_transfer,
+                routes::approval::set_approval_required,
+                routes::approval::request_review,
+                routes::approval::pending_reviews,
+                routes::approval::review,
+                routes::comment::get_comments,
+                routes::comment::new_comment,
+                routes::comment::resolve_comment,
+                routes::comment::delete_comment,
+                routes::project::get_projects,
+                routes::project::get_project,
+                routes::project::edit_project,
+                routes::project::invite,
+                routes::project::deinvite,
                 routes::notification::mark_as_read,
                 routes::notification::delete,
+                routes::organization::new_organization,
+                routes::organization::get_organizations,
+                routes::organization::get_organization,
+                routes::organization::delete_organization,
+                routes::organization::invite,
+                routes::organization::change_role,
+                routes::organization::deinvite,
+                routes::organization::add_capsule,
+                routes::organization::remove_capsule,
+                routes::tag::get_tags,
+                routes::tag::new_tag,
+                routes::tag::delete_tag,
+                routes::tag::add_tag,
+                routes::tag::remove_tag,
+                routes::tag::get_folders,
+                routes::tag::new_folder,
+                routes::tag::rename_folder,
+                routes::tag::move_folder,
+                routes::tag::delete_folder,
+                routes::tag::move_to_folder,
                 routes::admin::get_dashboard,
                 routes::admin::get_users,
                 routes::admin::get_search_users,
@@ -506,6 +618,16 @@ pub async fn rocket() -> StdResult<Rocket<Ignite>, rocket::Error> {
                 routes::admin::get_search_capsules,
                 routes::admin::request_invite_user,
                 routes::admin::delete_user,
+                routes::admin::change_plan,
+                routes::admin::change_disk_quota,
+                routes::admin::set_activated,
+                routes::admin::reset_password,
+                routes::admin::resend_activation,
+                routes::admin::impersonate,
+                routes::admin::reset_tasks,
+                routes::admin::rerun_tasks,
+                routes::admin::set_approver,
+                routes::admin::get_audit,
                 routes::admin::clear_websockets,
             ],
         )
@@ -515,7 +637,10 @@ pub async fn rocket() -> StdResult<Rocket<Ignite>, rocket::Error> {
 
     let socks = rocket.state::<WebSockets>().unwrap();
     let pool = rocket.state::<Pool>().unwrap();
+    let config = rocket.state::<Config>().unwrap();
     tokio::spawn(websocket(socks.clone(), pool.clone()));
+    tokio::spawn(sweep_trash(config.clone(), pool.clone()));
+    tokio::spawn(sweep_exports(config.clone(), pool.clone()));
 
     rocket.launch().await
 }
@@ -1,27 +1,72 @@
 //! This module contains the routes for admin management.
 
-use tokio::fs::remove_dir_all;
+use std::net::IpAddr;
+use std::sync::Arc;
 
+use chrono::{NaiveDateTime, Utc};
+
+use futures::stream;
 use futures::{poll, task::Poll, StreamExt};
 
+use ergol::prelude::*;
+use ergol::query::Filter;
+use ergol::Pool;
+
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
 use tungstenite::{Error as TError, Message};
 
-use rocket::http::Status;
-use rocket::serde::json::{json, Json, Value};
+use rocket::http::{CookieJar, Status};
+use rocket::serde::json::{from_value, json, Json, Value};
 use rocket::State as S;
 
 use serde::{Deserialize, Serialize};
 
 use crate::config::Config;
-use crate::db::capsule::Role;
-use crate::db::user::{Admin, User};
+use crate::db::approval::ApprovalStatus;
+use crate::db::audit::{AuditAction, AuditEvent};
+use crate::db::capsule::{capsule, Capsule, Role};
+use crate::db::session::Session;
+use crate::db::task_status::TaskStatus;
+use crate::db::user::{Admin, Plan, User};
+use crate::routes::capsule::{spawn_production, spawn_publication};
+use crate::routes::user::add_cookies;
 use crate::websockets::WebSockets;
-use crate::{Db, Error, Result};
+use crate::{Db, Error, HashId, Result, HARSH};
 
 /// Admin get dashboard
-#[get("/admin/dashboard")]
-pub async fn get_dashboard(admin: Admin, db: Db) -> Result<Value> {
-    admin.do_stats(&db).await
+///
+/// Besides the statistics of the database, returns the state of the task queue: the number of
+/// tasks holding a slot, and the number of running tasks waiting for one.
+#[get("/admin/dashboard?<period>&<count>")]
+pub async fn get_dashboard(
+    admin: Admin,
+    db: Db,
+    config: &S<Config>,
+    sem: &S<Arc<Semaphore>>,
+    period: Option<String>,
+    count: Option<i32>,
+) -> Result<Value> {
+    let period = period.unwrap_or_else(|| String::from("month"));
+    let mut stats = admin.do_stats(&period, count.unwrap_or(12), &db).await?;
+
+    let active = config
+        .concurrent_tasks
+        .saturating_sub(sem.available_permits());
+
+    let running = ["video_upload", "production", "publication"]
+        .iter()
+        .map(|x| stats["running_tasks"][x].as_i64().unwrap_or(0) as usize)
+        .sum::<usize>();
+
+    stats["queue"] = json!({
+        "slots": config.concurrent_tasks,
+        "active": active,
+        "waiting": running.saturating_sub(active),
+    });
+
+    Ok(stats)
 }
 
 /// Admin get pagniated users
@@ -96,30 +141,652 @@ pub async fn request_invite_user(
     db: Db,
     config: &S<Config>,
     form: Json<InviteUserForm>,
+    ip: Option<IpAddr>,
 ) -> Result<()> {
-    Ok(admin
+    let InviteUserForm { username, email } = form.0;
+    let target = Some(format!("user:{}", username));
+    let details = Some(email.clone());
+
+    admin
         .0
-        .request_invitation(form.0.username, form.0.email, &config.mailer, &db, &config)
-        .await?)
+        .request_invitation(username, email, &config.mailer, &db, &config)
+        .await?;
+
+    AuditEvent::log(
+        AuditAction::AdminInviteUser,
+        Some(&admin.0),
+        target,
+        details,
+        ip,
+        &db,
+    )
+    .await?;
+
+    Ok(())
 }
 
 /// The route that deletes a user
 #[delete("/admin/user/<id>")]
-pub async fn delete_user(_admin: Admin, db: Db, id: i32, config: &S<Config>) -> Result<()> {
+pub async fn delete_user(
+    admin: Admin,
+    db: Db,
+    id: i32,
+    config: &S<Config>,
+    ip: Option<IpAddr>,
+) -> Result<()> {
     let user = User::get_by_id(id, &db)
         .await?
         .ok_or(Error(Status::NotFound))?;
 
+    if user.id == admin.0.id {
+        return Err(Error(Status::BadRequest));
+    }
+
+    for export in user.data_exports(&db).await? {
+        export.remove(&config, &db).await?;
+    }
+
+    // Organization capsules stay with the organization.
+    for (organization, _) in user.organizations(&db).await? {
+        organization.reassign_capsules(&user, &db).await?;
+    }
+
+    // The capsules of the user are moved to the trash and handed to the admin, so that they can
+    // still be restored until the trash is swept. The capsules that were already in the trash are
+    // purged.
     let capsules = user.capsules(&db).await?;
-    for (capsule, role) in capsules {
-        if role == Role::Owner {
-            let dir = config.data_path.join(format!("{}", capsule.id));
-            remove_dir_all(dir).await?;
-            capsule.delete(&db).await?;
+    for (mut capsule, role) in capsules {
+        if role == Role::Owner && capsule.deleted.is_some() {
+            capsule.purge(&config, &db).await?;
+        } else if role == Role::Owner {
+            capsule.trash(&db).await?;
+
+            let current = capsule
+                .users(&db)
+                .await?
+                .into_iter()
+                .find(|(x, _)| x.id == admin.0.id);
+
+            if current.is_some() {
+                capsule.update_role(&admin.0, Role::Owner, &db).await?;
+            } else {
+                capsule.add_user(&admin.0, Role::Owner, &db).await?;
+            }
         }
     }
 
-    Ok(user.delete(&db).await?)
+    let (username, email) = (user.username.clone(), user.email.clone());
+    user.delete(&db).await?;
+
+    AuditEvent::log(
+        AuditAction::AdminDeleteUser,
+        Some(&admin.0),
+        Some(format!("user:{}", username)),
+        Some(email),
+        ip,
+        &db,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The form to change the plan of a user.
+#[derive(Serialize, Deserialize)]
+pub struct ChangePlanForm {
+    /// The new plan of the user.
+    plan: Plan,
+}
+
+/// The route that changes the plan of a user.
+///
+/// The disk quota of the user is reset to the default quota of the new plan.
+#[post("/admin/plan/<id>", data = "<form>")]
+pub async fn change_plan(
+    admin: Admin,
+    db: Db,
+    id: i32,
+    config: &S<Config>,
+    form: Json<ChangePlanForm>,
+    ip: Option<IpAddr>,
+) -> Result<Value> {
+    let mut user = User::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    let details = Some(format!("{:?} to {:?}", user.plan, form.plan));
+
+    user.plan = form.plan;
+    user.disk_quota = form.plan.default_disk_quota(&config);
+    user.save(&db).await?;
+
+    AuditEvent::log(
+        AuditAction::AdminChangePlan,
+        Some(&admin.0),
+        Some(format!("user:{}", user.username)),
+        details,
+        ip,
+        &db,
+    )
+    .await?;
+
+    user.admin_to_json(&db).await
+}
+
+/// The route that sets a custom disk quota (in GB) to a user.
+#[post("/admin/disk-quota/<id>/<quota>")]
+pub async fn change_disk_quota(
+    admin: Admin,
+    db: Db,
+    id: i32,
+    quota: i32,
+    ip: Option<IpAddr>,
+) -> Result<Value> {
+    if quota < 0 {
+        return Err(Error(Status::BadRequest));
+    }
+
+    let mut user = User::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    let details = Some(format!("{} to {}", user.disk_quota, quota));
+
+    user.disk_quota = quota;
+    user.save(&db).await?;
+
+    AuditEvent::log(
+        AuditAction::AdminChangeDiskQuota,
+        Some(&admin.0),
+        Some(format!("user:{}", user.username)),
+        details,
+        ip,
+        &db,
+    )
+    .await?;
+
+    user.admin_to_json(&db).await
+}
+
+/// The route that activates or deactivates a user.
+///
+/// Deactivating a user also logs them out.
+#[post("/admin/activate/<id>/<activated>")]
+pub async fn set_activated(
+    admin: Admin,
+    db: Db,
+    id: i32,
+    activated: bool,
+    ip: Option<IpAddr>,
+) -> Result<Value> {
+    let mut user = User::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    if user.id == admin.0.id {
+        return Err(Error(Status::BadRequest));
+    }
+
+    user.activated = activated;
+
+    if activated {
+        user.activation_key = None;
+        if user.activated_on.is_none() {
+            user.activated_on = Some(Utc::now().naive_utc());
+        }
+    } else {
+        for session in user.sessions(&db).await? {
+            session.delete(&db).await?;
+        }
+    }
+
+    user.save(&db).await?;
+
+    AuditEvent::log(
+        AuditAction::AdminSetActivated,
+        Some(&admin.0),
+        Some(format!("user:{}", user.username)),
+        Some(format!("{}", activated)),
+        ip,
+        &db,
+    )
+    .await?;
+
+    user.admin_to_json(&db).await
+}
+
+/// The route that sends a password reset email to a user.
+#[post("/admin/reset-password/<id>")]
+pub async fn reset_password(
+    admin: Admin,
+    db: Db,
+    id: i32,
+    config: &S<Config>,
+    ip: Option<IpAddr>,
+) -> Result<()> {
+    let mut user = User::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    user.request_change_password(&config.mailer, &db).await?;
+
+    AuditEvent::log(
+        AuditAction::AdminResetPassword,
+        Some(&admin.0),
+        Some(format!("user:{}", user.username)),
+        None,
+        ip,
+        &db,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The route that sends the activation email of a user again.
+#[post("/admin/resend-activation/<id>")]
+pub async fn resend_activation(
+    admin: Admin,
+    db: Db,
+    id: i32,
+    config: &S<Config>,
+    ip: Option<IpAddr>,
+) -> Result<()> {
+    let mut user = User::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    user.resend_activation(&config.mailer, &db).await?;
+
+    AuditEvent::log(
+        AuditAction::AdminResendActivation,
+        Some(&admin.0),
+        Some(format!("user:{}", user.username)),
+        None,
+        ip,
+        &db,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The route that lets an admin view the website as a user.
+///
+/// This creates a time-limited session for the user, flagged with the admin that created it. The
+/// admin gets their own session back when stopping the impersonation.
+#[post("/admin/impersonate/<id>")]
+pub async fn impersonate(
+    admin: Admin,
+    db: Db,
+    id: i32,
+    config: &S<Config>,
+    cookies: &CookieJar<'_>,
+    ip: Option<IpAddr>,
+) -> Result<()> {
+    let user = User::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    // Impersonating an admin would grant the same rights without the audit trail.
+    if user.plan == Plan::Admin || !user.activated {
+        return Err(Error(Status::BadRequest));
+    }
+
+    let session = Session::impersonate(&admin.0, &user, config.impersonation_minutes, &db).await?;
+
+    AuditEvent::log(
+        AuditAction::AdminStartImpersonation,
+        Some(&admin.0),
+        Some(format!("user:{}", user.username)),
+        Some(format!("{} minutes", config.impersonation_minutes)),
+        ip,
+        &db,
+    )
+    .await?;
+
+    add_cookies(&session.secret, &config, cookies);
+
+    Ok(())
+}
+
+/// The route that sets whether a user can approve capsules.
+#[post("/admin/approver/<id>/<approver>")]
+pub async fn set_approver(
+    admin: Admin,
+    db: Db,
+    id: i32,
+    approver: bool,
+    ip: Option<IpAddr>,
+) -> Result<()> {
+    let mut user = User::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    user.approver = approver;
+    user.save(&db).await?;
+
+    AuditEvent::log(
+        AuditAction::AdminSetApprover,
+        Some(&admin.0),
+        Some(format!("user:{}", user.username)),
+        Some(approver.to_string()),
+        ip,
+        &db,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The route that browses the audit log.
+///
+/// The `since` and `until` bounds are unix timestamps in seconds.
+#[get("/admin/audit?<page>&<action>&<actor>&<target>&<since>&<until>")]
+pub async fn get_audit(
+    _admin: Admin,
+    db: Db,
+    page: Option<i32>,
+    action: Option<String>,
+    actor: Option<String>,
+    target: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<Value> {
+    let action = match action {
+        Some(action) => {
+            Some(from_value::<AuditAction>(json!(action)).map_err(|_| Error(Status::BadRequest))?)
+        }
+        None => None,
+    };
+
+    let timestamp =
+        |x: i64| NaiveDateTime::from_timestamp_opt(x, 0).ok_or(Error(Status::BadRequest));
+    let since = since.map(timestamp).transpose()?;
+    let until = until.map(timestamp).transpose()?;
+
+    let events =
+        AuditEvent::search(action, actor, target, since, until, page.unwrap_or(0), &db).await?;
+
+    Ok(json!(events
+        .iter()
+        .map(|x| x.to_json())
+        .collect::<Vec<_>>()))
+}
+
+/// The route that resets the tasks of a capsule stuck in a running or waiting state.
+///
+/// If `kill` is set, the processes of the running tasks are killed first. Otherwise, the
+/// processes are assumed to be already gone, e.g. after a restart of the server.
+#[post("/admin/reset-tasks/<id>?<kill>")]
+pub async fn reset_tasks(
+    admin: Admin,
+    db: Db,
+    id: HashId,
+    kill: Option<bool>,
+    ip: Option<IpAddr>,
+) -> Result<Value> {
+    let mut capsule = Capsule::get_by_id(*id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    let pids = [
+        capsule.video_uploaded_pid.take(),
+        capsule.production_pid.take(),
+        capsule.publication_pid.take(),
+    ];
+
+    if kill.unwrap_or(false) {
+        for pid in pids.iter().flatten() {
+            Command::new("kill")
+                .arg(format!("{}", pid))
+                .output()
+                .await
+                .ok();
+        }
+    }
+
+    for status in [
+        &mut capsule.video_uploaded,
+        &mut capsule.produced,
+        &mut capsule.published,
+    ] {
+        if *status == TaskStatus::Running || *status == TaskStatus::Waiting {
+            *status = TaskStatus::Idle;
+        }
+    }
+
+    capsule.save(&db).await?;
+
+    AuditEvent::log(
+        AuditAction::AdminResetTasks,
+        Some(&admin.0),
+        Some(format!("capsule:{}", HARSH.encode(capsule.id))),
+        Some(format!("kill: {}", kill.unwrap_or(false))),
+        ip,
+        &db,
+    )
+    .await?;
+
+    capsule.to_json(Role::Owner, &db).await
+}
+
+/// The selection of capsules whose tasks are run again by an admin.
+#[derive(Serialize, Deserialize)]
+pub struct RerunForm {
+    /// Whether the capsules should be produced again.
+    #[serde(default)]
+    produce: bool,
+
+    /// Whether the capsules should be published again.
+    #[serde(default)]
+    publish: bool,
+
+    /// The ids of the capsules, all capsules matching the other filters if absent.
+    capsules: Option<Vec<String>>,
+
+    /// Only select capsules whose production is in this state.
+    produced: Option<TaskStatus>,
+
+    /// Only select capsules whose publication is in this state.
+    published: Option<TaskStatus>,
+}
+
+/// The route that runs the production or the publication of capsules again, e.g. after an
+/// upgrade of the encoder.
+///
+/// The tasks are queued like regular tasks, and their progress is sent to the admin on the
+/// websocket as `admin_rerun_progress` messages. Trashed capsules and capsules with a task
+/// already running are skipped. Producing again keeps the current publication, and only the
+/// published capsules that do not wait for an approval are published again.
+#[post("/admin/rerun", data = "<form>")]
+pub async fn rerun_tasks(
+    admin: Admin,
+    db: Db,
+    pool: &S<Pool>,
+    config: &S<Config>,
+    socks: &S<WebSockets>,
+    sem: &S<Arc<Semaphore>>,
+    form: Json<RerunForm>,
+    ip: Option<IpAddr>,
+) -> Result<Value> {
+    if !form.produce && !form.publish {
+        return Err(Error(Status::BadRequest));
+    }
+
+    let mut capsules = match &form.capsules {
+        Some(ids) => {
+            let mut capsules = vec![];
+            for id in ids {
+                let id = HARSH.decode(id)?;
+                capsules.push(
+                    Capsule::get_by_id(id, &db)
+                        .await?
+                        .ok_or(Error(Status::NotFound))?,
+                );
+            }
+            capsules
+        }
+        None => {
+            let mut filters: Vec<Filter> = vec![];
+
+            if let Some(produced) = form.produced {
+                filters.push(capsule::produced::eq(produced));
+            }
+
+            if let Some(published) = form.published {
+                filters.push(capsule::published::eq(published));
+            }
+
+            let mut query = Capsule::select();
+
+            if let Some(filter) = filters.into_iter().reduce(|acc, x| acc.and(x)) {
+                query = query.filter(filter);
+            }
+
+            query.execute(&db).await?
+        }
+    };
+
+    capsules.retain(|x| {
+        x.deleted.is_none()
+            && x.produced != TaskStatus::Running
+            && x.published != TaskStatus::Running
+            && (form.produce || x.produced == TaskStatus::Done)
+            && (form.produced.is_none() || Some(x.produced) == form.produced)
+            && (form.published.is_none() || Some(x.published) == form.published)
+    });
+
+    // Only the capsules that are currently published are published again, and only if their
+    // owner would be allowed to publish them.
+    let capsules = capsules
+        .into_iter()
+        .map(|x| {
+            let republish = form.publish
+                && x.published == TaskStatus::Done
+                && (!x.requires_approval(&config) || x.approval == ApprovalStatus::Approved);
+            (x, republish)
+        })
+        .filter(|(_, republish)| form.produce || *republish)
+        .collect::<Vec<_>>();
+
+    let total = capsules.len();
+
+    AuditEvent::log(
+        AuditAction::AdminRerunTasks,
+        Some(&admin.0),
+        None,
+        Some(format!(
+            "produce: {}, publish: {}, capsules: {}",
+            form.produce,
+            form.publish,
+            capsules
+                .iter()
+                .map(|(x, _)| HARSH.encode(x.id))
+                .collect::<Vec<_>>()
+                .join(",")
+        )),
+        ip,
+        &db,
+    )
+    .await?;
+
+    let produce = form.produce;
+    let pool = pool.inner().clone();
+    let config = config.inner().clone();
+    let socks = socks.inner().clone();
+    let sem = sem.inner().clone();
+    let admin = admin.0;
+
+    tokio::spawn(async move {
+        // At most as many capsules as there are task slots are handled at once, so that a rerun
+        // of many capsules neither starts all their processes nor takes all the connections.
+        let mut tasks = stream::iter(capsules)
+            .map(|(capsule, republish)| {
+                let (pool, config, socks, sem) =
+                    (pool.clone(), config.clone(), socks.clone(), sem.clone());
+
+                async move {
+                    let id = capsule.id;
+
+                    if produce {
+                        let owner = capsule.owner(&Db::from_pool(pool.clone()).await?).await?;
+                        let handle = spawn_production(
+                            capsule,
+                            owner,
+                            &config,
+                            pool.clone(),
+                            socks.clone(),
+                            sem.clone(),
+                        );
+
+                        if !handle.await.unwrap_or(false) {
+                            return Err(Error(Status::InternalServerError));
+                        }
+                    }
+
+                    if republish {
+                        let (capsule, owner) = {
+                            let db = Db::from_pool(pool.clone()).await?;
+                            let capsule = Capsule::get_by_id(id, &db)
+                                .await?
+                                .ok_or(Error(Status::NotFound))?;
+                            let owner = capsule.owner(&db).await?;
+                            (capsule, owner)
+                        };
+
+                        let handle = spawn_publication(capsule, owner, &config, pool, socks, sem);
+
+                        if !handle.await.unwrap_or(false) {
+                            return Err(Error(Status::InternalServerError));
+                        }
+                    }
+
+                    Ok::<(), Error>(())
+                }
+            })
+            .buffer_unordered(config.concurrent_tasks.max(1));
+
+        let (mut done, mut failed) = (0, 0);
+
+        while let Some(result) = tasks.next().await {
+            match result {
+                Ok(()) => done += 1,
+                Err(_) => failed += 1,
+            }
+
+            let text = json!({
+                "type": "admin_rerun_progress",
+                "done": done,
+                "failed": failed,
+                "total": total,
+            });
+
+            socks
+                .write_message(admin.id, Message::Text(text.to_string()))
+                .await
+                .ok();
+        }
+
+        drop(tasks);
+
+        if let Ok(db) = Db::from_pool(pool).await {
+            admin
+                .notify(
+                    &socks,
+                    "Relance terminée",
+                    &format!(
+                        "{} capsule(s) traitée(s) avec succès, {} échec(s).",
+                        done, failed
+                    ),
+                    &db,
+                )
+                .await
+                .ok();
+        }
+    });
+
+    Ok(json!({ "total": total }))
 }
 
 /// A routes that clears unused websockets.
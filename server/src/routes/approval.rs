@@ -0,0 +1,288 @@
+//! This module contains the routes of the review workflow of capsules before their publication.
+
+use serde::{Deserialize, Serialize};
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Json, Value};
+use rocket::State as S;
+
+use ergol::prelude::*;
+
+use crate::config::Config;
+use crate::db::approval::ApprovalStatus;
+use crate::db::capsule::{capsule, Capsule, Role};
+use crate::db::organization::{Organization, OrganizationRole};
+use crate::db::task_status::TaskStatus;
+use crate::db::user::{user, User};
+use crate::websockets::WebSockets;
+use crate::{Db, Error, HashId, Result};
+
+/// The route that sets whether a capsule must be approved before being published.
+///
+/// The requirement cannot be lifted when the instance enforces it, and only the admins of the
+/// organization owning the capsule can lift it on the capsules of an organization.
+#[post("/approval-required/<id>/<required>")]
+pub async fn set_approval_required(
+    user: User,
+    id: HashId,
+    required: bool,
+    config: &S<Config>,
+    db: Db,
+) -> Result<()> {
+    let (mut capsule, _) = user
+        .get_capsule_with_permission(*id, Role::Owner, &db)
+        .await?;
+
+    if !required {
+        if config.approval_required {
+            return Err(Error(Status::Forbidden));
+        }
+
+        if let Some(organization) = capsule.organization {
+            let organization = Organization::get_by_id(organization, &db)
+                .await?
+                .ok_or(Error(Status::NotFound))?;
+
+            if organization.role_of(&user, &db).await? != Some(OrganizationRole::Admin) {
+                return Err(Error(Status::Forbidden));
+            }
+        }
+    }
+
+    capsule.approval_required = required;
+    capsule.save(&db).await?;
+
+    Ok(())
+}
+
+/// The route that requests the review of a produced capsule.
+#[post("/request-review/<id>")]
+pub async fn request_review(user: User, id: HashId, db: Db, socks: &S<WebSockets>) -> Result<()> {
+    let (mut capsule, _) = user
+        .get_capsule_with_permission(*id, Role::Write, &db)
+        .await?;
+
+    if capsule.produced != TaskStatus::Done || capsule.approval == ApprovalStatus::Pending {
+        return Err(Error(Status::Conflict));
+    }
+
+    capsule.approval = ApprovalStatus::Pending;
+    capsule.approval_comment = None;
+    capsule.save(&db).await?;
+
+    let approvers = User::select()
+        .filter(user::approver::eq(true))
+        .execute(&db)
+        .await?;
+
+    for approver in approvers {
+        approver
+            .notify(
+                &socks,
+                "Relecture demandée",
+                &format!(
+                    "{} demande la relecture de la capsule \"{}\".",
+                    user.username, capsule.name
+                ),
+                &db,
+            )
+            .await
+            .ok();
+    }
+
+    capsule.notify_change(&db, &socks).await?;
+
+    Ok(())
+}
+
+/// The route that lists the capsules waiting for a review.
+#[get("/pending-reviews")]
+pub async fn pending_reviews(user: User, db: Db) -> Result<Value> {
+    if !user.approver {
+        return Err(Error(Status::Forbidden));
+    }
+
+    let capsules = Capsule::select()
+        .filter(capsule::approval::eq(ApprovalStatus::Pending))
+        .order_by(capsule::last_modified::descend())
+        .execute(&db)
+        .await?;
+
+    let mut json = vec![];
+    for capsule in capsules.into_iter().filter(|x| x.deleted.is_none()) {
+        json.push(capsule.to_json(Role::Read, &db).await?);
+    }
+
+    Ok(json!(json))
+}
+
+/// The json format to approve or reject a capsule.
+#[derive(Serialize, Deserialize)]
+pub struct Review {
+    /// Whether the capsule is approved or rejected.
+    pub approved: bool,
+
+    /// The comment of the approver.
+    pub comment: Option<String>,
+}
+
+/// The route that approves or rejects a capsule waiting for a review.
+#[post("/review/<id>", data = "<data>")]
+pub async fn review(
+    user: User,
+    id: HashId,
+    db: Db,
+    socks: &S<WebSockets>,
+    data: Json<Review>,
+) -> Result<()> {
+    if !user.approver {
+        return Err(Error(Status::Forbidden));
+    }
+
+    let mut capsule = Capsule::get_by_id(*id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    let involved = capsule
+        .users(&db)
+        .await?
+        .iter()
+        .any(|(member, _)| member.id == user.id);
+
+    let trashed = capsule.deleted.is_some();
+    check_review(capsule.approval, trashed, involved, &data)?;
+
+    let Review { approved, comment } = data.0;
+
+    capsule.approval = if approved {
+        ApprovalStatus::Approved
+    } else {
+        ApprovalStatus::Rejected
+    };
+    capsule.approval_comment = comment;
+    capsule.save(&db).await?;
+
+    let (title, message) = if approved {
+        (
+            "Capsule approuvée",
+            format!("La capsule \"{}\" a été approuvée.", capsule.name),
+        )
+    } else {
+        (
+            "Capsule refusée",
+            format!("La capsule \"{}\" a été refusée.", capsule.name),
+        )
+    };
+
+    for (member, role) in capsule.users(&db).await? {
+        if role >= Role::Write {
+            member.notify(&socks, title, &message, &db).await.ok();
+        }
+    }
+
+    capsule.notify_change(&db, &socks).await?;
+
+    Ok(())
+}
+
+/// Checks that a capsule can be approved or rejected by a reviewer.
+///
+/// The capsule must be waiting for a review and not be in the trash, the reviewer must have no
+/// role on it, and a rejection must be explained.
+fn check_review(
+    approval: ApprovalStatus,
+    trashed: bool,
+    involved: bool,
+    review: &Review,
+) -> Result<()> {
+    if trashed {
+        return Err(Error(Status::NotFound));
+    }
+
+    if approval != ApprovalStatus::Pending {
+        return Err(Error(Status::Conflict));
+    }
+
+    if involved {
+        return Err(Error(Status::Forbidden));
+    }
+
+    let explained = review
+        .comment
+        .as_ref()
+        .map(|x| !x.trim().is_empty())
+        .unwrap_or(false);
+
+    if !review.approved && !explained {
+        return Err(Error(Status::BadRequest));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a review approving or rejecting a capsule with a comment.
+    fn review(approved: bool, comment: Option<&str>) -> Review {
+        Review {
+            approved,
+            comment: comment.map(String::from),
+        }
+    }
+
+    /// Returns the status of the error of a review check, if any.
+    fn status(result: Result<()>) -> Option<Status> {
+        result.err().map(|x| x.0)
+    }
+
+    #[test]
+    fn pending_capsules_can_be_approved_or_rejected_with_a_comment() {
+        let pending = ApprovalStatus::Pending;
+        assert!(check_review(pending, false, false, &review(true, None)).is_ok());
+        assert!(check_review(pending, false, false, &review(false, Some("Typo"))).is_ok());
+    }
+
+    #[test]
+    fn rejections_must_be_explained() {
+        let pending = ApprovalStatus::Pending;
+        let blank = review(false, Some("  "));
+        assert_eq!(
+            status(check_review(pending, false, false, &review(false, None))),
+            Some(Status::BadRequest)
+        );
+        assert_eq!(
+            status(check_review(pending, false, false, &blank)),
+            Some(Status::BadRequest)
+        );
+    }
+
+    #[test]
+    fn only_pending_capsules_can_be_reviewed() {
+        for approval in [ApprovalStatus::Approved, ApprovalStatus::Rejected].iter() {
+            assert_eq!(
+                status(check_review(*approval, false, false, &review(true, None))),
+                Some(Status::Conflict)
+            );
+        }
+    }
+
+    #[test]
+    fn trashed_capsules_cannot_be_reviewed() {
+        let pending = ApprovalStatus::Pending;
+        assert_eq!(
+            status(check_review(pending, true, false, &review(true, None))),
+            Some(Status::NotFound)
+        );
+    }
+
+    #[test]
+    fn reviewers_cannot_review_their_own_capsules() {
+        let pending = ApprovalStatus::Pending;
+        assert_eq!(
+            status(check_review(pending, false, true, &review(true, None))),
+            Some(Status::Forbidden)
+        );
+    }
+}
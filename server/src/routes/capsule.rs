@@ -1,31 +1,53 @@
 //! This module contains the routes to manage the capsules.
 
-use std::process::Stdio;
+use std::net::IpAddr;
+use std::path::Path;
+use std::process::{Output, Stdio};
 use std::sync::Arc;
 
+use chrono::Utc;
+
 use uuid::Uuid;
 
 use serde::{Deserialize, Serialize};
 
-use tokio::fs::{create_dir_all, remove_dir_all};
+use futures::future::try_join_all;
+
+use tokio::fs::{create_dir_all, remove_dir_all, remove_file, write};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
 use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
 
 use ergol::tokio_postgres::types::Json as EJson;
+use ergol::Pool;
 
 use rocket::data::ToByteUnit;
+use rocket::form::Form;
+use rocket::fs::TempFile;
 use rocket::http::{ContentType, Status};
-use rocket::serde::json::{json, Json, Value};
+use rocket::serde::json::{from_value, json, Json, Value};
 use rocket::{Data, State as S};
 
-use crate::command::{export_slides, run_command};
+use crate::command::{
+    count_pages, export_slides, import_audio, import_images, markdown_to_pdf, office_notes,
+    office_to_pdf, pdf_notes, run_command, split_scenes, unzip_images, MAX_UNZIPPED_SIZE,
+};
 use crate::config::Config;
-use crate::db::capsule::{Capsule, Fade, Gos, Privacy, Record, Role, Slide, WebcamSettings};
+use crate::db::approval::ApprovalStatus;
+use crate::db::audit::{AuditAction, AuditEvent};
+use crate::db::capsule::{
+    check_slide_durations, check_transitions, AudioTrack, Capsule, CapsuleListing, CapsuleSort,
+    Event, EventType, Gos, OutputSettings, Privacy, Record, Role, Slide, WebcamSettings,
+};
+use crate::db::invitation::PendingInvitation;
+use crate::db::project::Project;
+use crate::db::session::{Impersonation, NotImpersonated};
 use crate::db::task_status::TaskStatus;
+use crate::db::transfer::OwnershipTransfer;
 use crate::db::user::User;
 use crate::websockets::WebSockets;
-use crate::{Db, Error, HashId, Result};
+use crate::{Db, Error, HashId, Lang, Result, HARSH};
 
 /// The route that gives the capsule information.
 #[get("/capsule/<capsule_id>")]
@@ -37,6 +59,60 @@ pub async fn get_capsule(user: User, capsule_id: HashId, db: Db) -> Result<Value
     capsule.to_json(role, &db).await
 }
 
+/// The route that lists the capsules of the user, a page at a time.
+///
+/// The capsules can be filtered by project (given by its id), role, production and publication
+/// status, privacy, and by the tags and folders of the user, and sorted by `name`, `last_modified` (the default)
+/// or `duration`.
+#[get("/capsules?<page>&<project>&<role>&<produced>&<published>&<privacy>&<tag>&<folder>&<sort>&<desc>")]
+pub async fn get_capsules(
+    user: User,
+    db: Db,
+    page: Option<i64>,
+    project: Option<String>,
+    role: Option<String>,
+    produced: Option<String>,
+    published: Option<String>,
+    privacy: Option<String>,
+    tag: Option<i32>,
+    folder: Option<i32>,
+    sort: Option<String>,
+    desc: Option<bool>,
+) -> Result<Value> {
+    fn parse<T: serde::de::DeserializeOwned>(x: Option<String>) -> Result<Option<T>> {
+        x.map(|x| from_value::<T>(json!(x)).map_err(|_| Error(Status::BadRequest)))
+            .transpose()
+    }
+
+    let sort = parse(sort)?.unwrap_or(CapsuleSort::LastModified);
+
+    let listing = CapsuleListing {
+        project: project.map(|x| HARSH.decode(&x)).transpose()?,
+        role: parse(role)?,
+        produced: parse(produced)?,
+        published: parse(published)?,
+        privacy: parse(privacy)?,
+        tag,
+        folder,
+        sort,
+        // Names are sorted alphabetically, the others start with the most recent or longest.
+        descending: desc.unwrap_or(sort != CapsuleSort::Name),
+        page: page.unwrap_or(0),
+    };
+
+    listing.execute(&user, &db).await
+}
+
+/// The route that searches the capsules of the user by name, project and prompts.
+#[get("/search?<q>")]
+pub async fn search_capsules(user: User, db: Db, lang: Lang, q: String) -> Result<Value> {
+    if q.trim().is_empty() {
+        return Err(Error(Status::BadRequest));
+    }
+
+    user.search_capsules(&q, &lang.0, &db).await
+}
+
 /// The route that creates an empty capsule.
 #[post("/empty-capsule/<project_name>/<capsule_name>")]
 pub async fn empty_capsule(
@@ -58,14 +134,32 @@ pub async fn empty_capsule(
     Ok(capsule.to_json(Role::Owner, &db).await?)
 }
 
-/// The route that creates a capsule from PDF slides.
-#[post("/new-capsule/<project_name>/<capsule_name>", data = "<data>")]
+/// Returns the extension of an office presentation from its content type, if it is one.
+fn office_extension(content_type: &ContentType) -> Option<&'static str> {
+    match (content_type.top().as_str(), content_type.sub().as_str()) {
+        ("application", "vnd.openxmlformats-officedocument.presentationml.presentation") => {
+            Some("pptx")
+        }
+        ("application", "vnd.ms-powerpoint") => Some("ppt"),
+        ("application", "vnd.oasis.opendocument.presentation") => Some("odp"),
+        _ => None,
+    }
+}
+
+/// The route that creates a capsule from slides.
+///
+/// The slides are a PDF file, or an office presentation (pptx, ppt or odp) that is converted to
+/// PDF first. The prompts are prefilled with the speaker notes of office presentations, or the
+/// annotations of PDF pages, and with the text of the slides if `text` is set.
+#[post("/new-capsule/<project_name>/<capsule_name>?<text>", data = "<data>")]
 pub async fn new_capsule(
     user: User,
     project_name: String,
     capsule_name: String,
+    text: Option<bool>,
     db: Db,
     config: &S<Config>,
+    content_type: Option<&ContentType>,
     data: Data<'_>,
 ) -> Result<Value> {
     let mut capsule = Capsule::new(project_name, &capsule_name, &user, &db).await?;
@@ -77,22 +171,372 @@ pub async fn new_capsule(
 
     create_dir_all(&path).await?;
 
-    let tmp = path.join(format!("{}.pdf", Uuid::new_v4()));
+    let extension = content_type.and_then(office_extension);
+
+    let tmp = path.join(format!("{}.{}", Uuid::new_v4(), extension.unwrap_or("pdf")));
 
     data.open(1_i32.gibibytes()).into_file(&tmp).await?;
 
-    let gos = export_slides(&config, tmp, path, None)?
+    let size = capsule.output_settings(&config).size();
+
+    // The conversions run external programs and take a while, they must not block the runtime.
+    let result = {
+        let config = config.inner().clone();
+        let text = text.unwrap_or(false);
+
+        tokio::task::spawn_blocking(move || {
+            let (pdf, prompts) = match extension {
+                Some(_) => {
+                    // Old ppt files have no notes we can read, the slides are imported anyway.
+                    let prompts = office_notes(&tmp).unwrap_or_else(|_| vec![]);
+                    let pdf = office_to_pdf(&tmp, &path)?;
+                    std::fs::remove_file(&tmp).ok();
+                    (pdf, prompts)
+                }
+                None => (tmp, vec![]),
+            };
+
+            let notes = pdf_notes(&pdf, text).unwrap_or_else(|e| {
+                warn!("Could not extract the notes of the PDF: {:?}", e);
+                vec![]
+            });
+
+            let slides = export_slides(&config, pdf, path, None, &size)?;
+            Ok::<_, Error>((slides, prompts, notes))
+        })
+        .await
+        .unwrap_or(Err(Error(Status::InternalServerError)))
+    };
+
+    let (slides, prompts, notes) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            // The slides could not be imported, the capsule is removed right away.
+            capsule.purge(&config, &db).await?;
+            return Err(e);
+        }
+    };
+
+    let gos = slides
+        .into_iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let prompt = match prompts.get(i) {
+                Some(prompt) if !prompt.is_empty() => prompt.clone(),
+                _ => notes.get(i).cloned().unwrap_or_default(),
+            };
+
+            Gos::from_slide(x, prompt)
+        })
+        .collect::<Vec<_>>();
+
+    capsule.structure = EJson(gos);
+    capsule.set_changed();
+    capsule.save(&db).await?;
+
+    Ok(capsule.to_json(Role::Owner, &db).await?)
+}
+
+/// A part of a file name, used to sort file names in natural order.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum NameChunk {
+    /// A number, compared by value.
+    Number(u64),
+
+    /// Text, compared case insensitively.
+    Text(String),
+}
+
+/// Splits a file name in chunks so that e.g. `slide2.png` is sorted before `slide10.png`.
+fn natural_key(name: &str) -> Vec<NameChunk> {
+    let mut chunks = vec![];
+    let mut chars = name.chars().peekable();
+
+    while let Some(c) = chars.peek().copied() {
+        let digit = c.is_ascii_digit();
+        let mut chunk = String::new();
+
+        while let Some(c) = chars.peek().copied() {
+            if c.is_ascii_digit() != digit {
+                break;
+            }
+            chunk.push(c);
+            chars.next();
+        }
+
+        chunks.push(if digit {
+            NameChunk::Number(chunk.parse().unwrap_or(u64::MAX))
+        } else {
+            NameChunk::Text(chunk.to_lowercase())
+        });
+    }
+
+    chunks
+}
+
+/// The files from which a capsule is created with images.
+#[derive(FromForm)]
+pub struct ImagesUpload<'r> {
+    /// The images, or zip archives containing images.
+    files: Vec<TempFile<'r>>,
+}
+
+/// The route that creates a capsule from images, with one gos per image.
+///
+/// The images can be sent directly or in zip archives, and are sorted by file name. This is a
+/// multipart form, the `data-form` and `file` limits of rocket must be large enough.
+#[post(
+    "/new-capsule-from-images/<project_name>/<capsule_name>",
+    data = "<form>"
+)]
+pub async fn new_capsule_from_images(
+    user: User,
+    project_name: String,
+    capsule_name: String,
+    db: Db,
+    config: &S<Config>,
+    form: Form<ImagesUpload<'_>>,
+) -> Result<Value> {
+    let mut files = form.into_inner().files;
+
+    let supported = |x: &TempFile| match x.content_type() {
+        Some(content_type) => content_type.top() == "image" || *content_type == ContentType::ZIP,
+        None => false,
+    };
+
+    if files.is_empty() || !files.iter().all(supported) {
+        return Err(Error(Status::UnsupportedMediaType));
+    }
+
+    let mut capsule = Capsule::new(project_name, &capsule_name, &user, &db).await?;
+
+    let path = config
+        .data_path
+        .join(format!("{}", capsule.id))
+        .join("assets");
+
+    let upload = path.join(format!("{}", Uuid::new_v4()));
+
+    // Every failure goes through the purge of the capsule below.
+    let slides: Result<Vec<Uuid>> = async {
+        create_dir_all(&upload).await?;
+
+        let mut images = vec![];
+
+        let mut budget = MAX_UNZIPPED_SIZE;
+
+        for (i, file) in files.iter_mut().enumerate() {
+            let name = file
+                .raw_name()
+                .map(|x| x.dangerous_unsafe_unsanitized_raw().as_str().to_string())
+                .unwrap_or_default();
+
+            let zip = file.content_type() == Some(&ContentType::ZIP);
+
+            let tmp = upload.join(format!("{}", i));
+            file.copy_to(&tmp).await?;
+
+            if zip {
+                let output = upload.join(format!("{}-content", i));
+                images.extend(unzip_images(&tmp, output, &mut budget)?);
+            } else {
+                images.push((name, tmp));
+            }
+        }
+
+        images.sort_by_key(|(name, _)| natural_key(name));
+
+        let paths = images.into_iter().map(|(_, x)| x).collect::<Vec<_>>();
+        import_images(
+            &config,
+            &paths,
+            &path,
+            &capsule.output_settings(&config).size(),
+        )
+    }
+    .await;
+
+    remove_dir_all(&upload).await.ok();
+
+    let slides = match slides {
+        Ok(slides) if slides.is_empty() => Err(Error(Status::BadRequest)),
+        x => x,
+    };
+
+    let slides = match slides {
+        Ok(slides) => slides,
+        Err(e) => {
+            // No slide could be imported, the capsule is removed right away.
+            capsule.purge(&config, &db).await?;
+            return Err(e);
+        }
+    };
+
+    capsule.structure = EJson(
+        slides
+            .into_iter()
+            .map(|x| Gos::from_slide(x, String::new()))
+            .collect(),
+    );
+    capsule.set_changed();
+    capsule.save(&db).await?;
+
+    Ok(capsule.to_json(Role::Owner, &db).await?)
+}
+
+/// Splits a markdown document in slides and their prompts.
+///
+/// Slides are separated by `---` lines surrounded by blank lines, outside of fenced code blocks,
+/// and a leading front matter block is skipped. The lines after a line starting with `Note:` are
+/// the prompt of the slide, one sentence per line.
+fn parse_markdown_slides(markdown: &str) -> Vec<(String, String)> {
+    let lines = markdown.lines().collect::<Vec<_>>();
+    let is_blank = |i: usize| lines.get(i).map(|x| x.trim().is_empty()).unwrap_or(true);
+
+    let mut start = 0;
+    if lines.first().map(|x| x.trim_end()) == Some("---") {
+        let end = lines[1..]
+            .iter()
+            .position(|x| matches!(x.trim_end(), "---" | "..."));
+
+        if let Some(end) = end {
+            start = end + 2;
+        }
+    }
+
+    let mut slides = vec![];
+    let (mut content, mut notes) = (vec![], vec![]);
+    let mut in_notes = false;
+    let mut fence: Option<&str> = None;
+
+    for i in start..=lines.len() {
+        let line = lines.get(i).copied().unwrap_or("");
+        let trimmed = line.trim();
+
+        let separator = i == lines.len()
+            || (fence.is_none()
+                && trimmed == "---"
+                && (i == start || is_blank(i - 1))
+                && is_blank(i + 1));
+
+        if separator {
+            let slide = (content.join("\n"), notes.join("\n"));
+
+            if !slide.0.trim().is_empty() || !slide.1.is_empty() {
+                slides.push(slide);
+            }
+
+            content.clear();
+            notes.clear();
+            in_notes = false;
+            continue;
+        }
+
+        match fence {
+            Some(marker) if trimmed.starts_with(marker) => fence = None,
+            None if trimmed.starts_with("```") => fence = Some("```"),
+            None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+            _ => (),
+        }
+
+        if let (None, Some(note)) = (fence, trimmed.strip_prefix("Note:")) {
+            in_notes = true;
+            if !note.trim().is_empty() {
+                notes.push(note.trim());
+            }
+        } else if in_notes {
+            if !trimmed.is_empty() {
+                notes.push(trimmed);
+            }
+        } else {
+            content.push(line);
+        }
+    }
+
+    slides
+}
+
+/// Checks that a PDF rendered from markdown has one page per parsed slide.
+///
+/// Otherwise the prompts would be attached to the wrong slides, e.g. when a slide overflows on
+/// two pages.
+fn check_page_count<P: AsRef<Path>>(pdf: P, slides: usize) -> Result<()> {
+    let pages = count_pages(pdf)? as usize;
+
+    if pages != slides {
+        warn!(
+            "The markdown slides were rendered on {} pages instead of {}",
+            pages, slides
+        );
+        return Err(Error(Status::UnprocessableEntity));
+    }
+
+    Ok(())
+}
+
+/// The route that creates a capsule from markdown slides.
+///
+/// The slides are rendered with pandoc, and their notes become the prompts.
+#[post(
+    "/new-capsule-from-markdown/<project_name>/<capsule_name>",
+    data = "<data>"
+)]
+pub async fn new_capsule_from_markdown(
+    user: User,
+    project_name: String,
+    capsule_name: String,
+    db: Db,
+    config: &S<Config>,
+    data: Data<'_>,
+) -> Result<Value> {
+    let markdown = data.open(10_i32.mebibytes()).into_string().await?;
+    let slides = parse_markdown_slides(&markdown);
+
+    if slides.is_empty() {
+        return Err(Error(Status::BadRequest));
+    }
+
+    let mut capsule = Capsule::new(project_name, &capsule_name, &user, &db).await?;
+
+    let path = config
+        .data_path
+        .join(format!("{}", capsule.id))
+        .join("assets");
+
+    create_dir_all(&path).await?;
+
+    let uuid = Uuid::new_v4();
+    let input = path.join(format!("{}.md", uuid));
+    let pdf = path.join(format!("{}.pdf", uuid));
+
+    let rendered = slides
+        .iter()
+        .map(|(content, _)| content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n---\n\n");
+
+    write(&input, rendered).await?;
+
+    let size = capsule.output_settings(&config).size();
+    let result = markdown_to_pdf(&input, &pdf)
+        .and_then(|_| check_page_count(&pdf, slides.len()))
+        .and_then(|_| export_slides(&config, pdf, &path, None, &size));
+
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            // The slides could not be rendered, the capsule is removed right away.
+            capsule.purge(&config, &db).await?;
+            return Err(e);
+        }
+    };
+
+    let gos = result
         .into_iter()
-        .map(|x| Gos {
-            record: None,
-            slides: vec![Slide {
-                uuid: x,
-                extra: None,
-                prompt: String::new(),
-            }],
-            events: vec![],
-            webcam_settings: WebcamSettings::default(),
-            fade: Fade::none(),
+        .enumerate()
+        .map(|(i, x)| {
+            let prompt = slides.get(i).map(|(_, x)| x.clone()).unwrap_or_default();
+            Gos::from_slide(x, prompt)
         })
         .collect::<Vec<_>>();
 
@@ -103,6 +547,142 @@ pub async fn new_capsule(
     Ok(capsule.to_json(Role::Owner, &db).await?)
 }
 
+/// The route that creates a capsule from an existing video, e.g. an old lecture.
+///
+/// The slide changes of the video are detected with scene detection, a still of each slide
+/// becomes a slide of the only gos of the capsule, and the video becomes its record, with a
+/// `next_slide` event at each slide change. The `threshold` of the scene detection is between 0
+/// and 1, lower values detect more changes.
+#[post(
+    "/new-capsule-from-video/<project_name>/<capsule_name>?<threshold>",
+    data = "<data>"
+)]
+pub async fn new_capsule_from_video(
+    user: User,
+    project_name: String,
+    capsule_name: String,
+    threshold: Option<f32>,
+    db: Db,
+    config: &S<Config>,
+    data: Data<'_>,
+) -> Result<Value> {
+    let threshold = threshold.unwrap_or(0.3);
+
+    // Written so that NaN is rejected too.
+    if !(threshold > 0.0 && threshold < 1.0) {
+        return Err(Error(Status::BadRequest));
+    }
+
+    let mut capsule = Capsule::new(project_name, &capsule_name, &user, &db).await?;
+
+    let path = config
+        .data_path
+        .join(format!("{}", capsule.id))
+        .join("assets");
+
+    create_dir_all(&path).await?;
+
+    // The record is named like the records of the website, ffmpeg does not rely on the
+    // extension to read it.
+    let uuid = Uuid::new_v4();
+    let video = path.join(format!("{}.webm", uuid));
+    data.open(4_i32.gibibytes()).into_file(&video).await?;
+
+    let stills = path.join(format!("{}", Uuid::new_v4()));
+    let output_size = capsule.output_settings(&config).size();
+
+    // The transcoding and the scene detection take a while, they must not block the runtime.
+    let result = {
+        let config = config.inner().clone();
+        let id = capsule.id;
+        let (path, stills) = (path.clone(), stills.clone());
+
+        tokio::task::spawn_blocking(move || {
+            run_command(&vec![
+                "../scripts/psh",
+                "on-record",
+                &format!("{}", id),
+                &format!("{}", uuid),
+            ])
+            .and_then(|output| record_size(&output))
+            .and_then(|size| {
+                let scenes = split_scenes(&video, &stills, threshold)?;
+                let paths = scenes.iter().map(|(_, x)| x).collect::<Vec<_>>();
+                let slides = import_images(&config, &paths, &path, &output_size)?;
+
+                let duration = run_command(&vec![
+                    "../scripts/psh",
+                    "duration",
+                    video.to_str().ok_or(Error(Status::InternalServerError))?,
+                ])?;
+
+                let duration = (std::str::from_utf8(&duration.stdout)?
+                    .trim()
+                    .parse::<f32>()
+                    .map_err(|_| Error(Status::InternalServerError))?
+                    * 1000.) as i32;
+
+                Ok((size, scenes, slides, duration))
+            })
+        })
+        .await
+        .unwrap_or(Err(Error(Status::InternalServerError)))
+    };
+
+    remove_dir_all(&stills).await.ok();
+
+    let (size, scenes, slides, duration) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            // The video could not be imported, the capsule is removed right away.
+            capsule.purge(&config, &db).await?;
+            return Err(e);
+        }
+    };
+
+    let mut events = vec![Event {
+        ty: EventType::Start,
+        time: 0,
+    }];
+
+    events.extend(scenes.iter().skip(1).map(|(time, _)| Event {
+        ty: EventType::NextSlide,
+        time: *time,
+    }));
+
+    events.push(Event {
+        ty: EventType::End,
+        time: duration,
+    });
+
+    let mut gos = Gos::new();
+    gos.record = Some(Record {
+        uuid,
+        size,
+        pointer_uuid: None,
+    });
+    gos.slides = slides
+        .into_iter()
+        .map(|x| Slide::new(x, String::new()))
+        .collect();
+    gos.events = events;
+
+    // The video is shown as is until the user changes the settings.
+    gos.webcam_settings = match size {
+        Some(_) => WebcamSettings::Fullscreen {
+            opacity: 1.0,
+            keycolor: None,
+        },
+        None => WebcamSettings::Disabled,
+    };
+
+    capsule.structure = EJson(vec![gos]);
+    capsule.set_changed();
+    capsule.save(&db).await?;
+
+    Ok(capsule.to_json(Role::Owner, &db).await?)
+}
+
 /// The json format to edit the content of a capsule.
 #[derive(Serialize, Deserialize)]
 pub struct CapsuleEdit {
@@ -121,6 +701,10 @@ pub struct CapsuleEdit {
     /// Whether the subtitles should be generated from the prompt.
     pub prompt_subtitles: bool,
 
+    /// The duration of the slides without record that do not specify one.
+    #[serde(default)]
+    pub slide_duration_ms: Option<i32>,
+
     /// The new structure of the capsule.
     pub structure: Vec<Gos>,
 }
@@ -140,16 +724,24 @@ pub async fn edit_capsule(
         structure,
         privacy,
         prompt_subtitles,
+        slide_duration_ms,
     } = data.0;
 
+    check_slide_durations(slide_duration_ms, &structure)?;
+    check_transitions(&structure)?;
+
     let (mut capsule, _) = user
         .get_capsule_with_permission(*id, Role::Write, &db)
         .await?;
 
-    capsule.project = project;
+    if capsule.project_name(&db).await? != project {
+        Project::move_capsule(&mut capsule, &project, &db).await?;
+    }
+
     capsule.name = name;
     capsule.privacy = privacy;
     capsule.prompt_subtitles = prompt_subtitles;
+    capsule.slide_duration_ms = slide_duration_ms;
     capsule.structure = EJson(structure);
     capsule.set_changed();
     capsule.save(&db).await?;
@@ -159,42 +751,185 @@ pub async fn edit_capsule(
     Ok(())
 }
 
-/// The route that deletes a capsule by id.
+/// The route that sets the output format of a capsule.
+///
+/// Sending null resets the capsule to the default format of the server.
+#[post("/output-settings/<id>", data = "<data>")]
+pub async fn set_output_settings(
+    user: User,
+    id: HashId,
+    data: Json<Option<OutputSettings>>,
+    db: Db,
+    socks: &S<WebSockets>,
+) -> Result<Value> {
+    let (mut capsule, role) = user
+        .get_capsule_with_permission(*id, Role::Write, &db)
+        .await?;
+
+    if let Some(settings) = data.0 {
+        settings.check(capsule.owner(&db).await?.plan)?;
+    }
+
+    capsule.output_settings = data.0.map(EJson);
+    capsule.set_changed();
+    capsule.save(&db).await?;
+
+    capsule.notify_change(&db, &socks).await?;
+
+    capsule.to_json(role, &db).await
+}
+
+/// The route that moves a capsule to the trash.
 #[delete("/capsule/<id>")]
-pub async fn delete_capsule(user: User, db: Db, id: HashId, config: &S<Config>) -> Result<()> {
-    let (capsule, _) = user
+pub async fn delete_capsule(
+    user: User,
+    db: Db,
+    id: HashId,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
+) -> Result<()> {
+    let (mut capsule, _) = user
         .get_capsule_with_permission(*id, Role::Owner, &db)
         .await?;
 
-    capsule.delete(&db).await?;
-    let dir = config.data_path.join(format!("{}", *id));
-    remove_dir_all(dir).await?;
+    capsule.trash(&db).await?;
+
+    AuditEvent::log(
+        AuditAction::DeleteCapsule,
+        Some(&user),
+        Some(format!("capsule:{}", HARSH.encode(capsule.id))),
+        Some(capsule.name.clone()),
+        ip,
+        &db,
+    )
+    .await?;
+
     Ok(())
 }
 
-/// The route that deletes a whole project.
-#[delete("/project/<name>")]
-pub async fn delete_project(user: User, db: Db, config: &S<Config>, name: String) -> Result<()> {
-    let capsules = user.capsules(&db).await?;
+/// The route that deletes a whole project, moving the capsules owned by the user to the trash.
+///
+/// The other capsules are detached from the project and the roles granted through the project
+/// are revoked. The capsules restored from the trash get a new project.
+#[delete("/project/<id>")]
+pub async fn delete_project(
+    user: User,
+    db: Db,
+    id: HashId,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
+) -> Result<()> {
+    let (project, _) = user
+        .get_project_with_permission(*id, Role::Owner, &db)
+        .await?;
 
-    for (capsule, role) in capsules {
-        if role != Role::Owner {
-            continue;
-        }
+    for (member, _) in project.users(&db).await? {
+        project.unshare(&member, &db).await?;
+    }
 
-        if capsule.project != name {
+    for mut capsule in project.capsules(&db).await? {
+        if user
+            .get_capsule_with_permission(capsule.id, Role::Owner, &db)
+            .await
+            .is_err()
+        {
+            Project::detach_capsule(&mut capsule, &db).await?;
             continue;
         }
 
-        // Delete the capsule
-        let dir = config.data_path.join(format!("{}", capsule.id));
-        remove_dir_all(dir).await?;
-        capsule.delete(&db).await?;
+        capsule.trash(&db).await?;
     }
 
+    let (id, name) = (project.id, project.name.clone());
+
+    project.delete(&db).await?;
+
+    AuditEvent::log(
+        AuditAction::DeleteProject,
+        Some(&user),
+        Some(format!("project:{}", HARSH.encode(id))),
+        Some(name),
+        ip,
+        &db,
+    )
+    .await?;
+
     Ok(())
 }
 
+/// The route that restores a capsule from the trash.
+#[post("/restore-capsule/<id>")]
+pub async fn restore_capsule(
+    user: User,
+    db: Db,
+    id: HashId,
+    ip: Option<IpAddr>,
+    impersonation: Impersonation,
+) -> Result<Value> {
+    let mut capsule = user.get_trashed_capsule(*id, &db).await?;
+
+    capsule.restore(&db).await?;
+
+    AuditEvent::log(
+        AuditAction::RestoreCapsule,
+        Some(impersonation.actor(&user)),
+        Some(format!("capsule:{}", HARSH.encode(capsule.id))),
+        Some(capsule.name.clone()),
+        ip,
+        &db,
+    )
+    .await?;
+
+    capsule.to_json(Role::Owner, &db).await
+}
+
+/// The route that deletes a capsule from the trash for good.
+#[delete("/trash/<id>")]
+pub async fn purge_capsule(
+    user: User,
+    db: Db,
+    id: HashId,
+    config: &S<Config>,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
+) -> Result<()> {
+    let capsule = user.get_trashed_capsule(*id, &db).await?;
+
+    let target = Some(format!("capsule:{}", HARSH.encode(capsule.id)));
+    let name = Some(capsule.name.clone());
+
+    capsule.purge(&config, &db).await?;
+
+    AuditEvent::log(
+        AuditAction::PurgeCapsule,
+        Some(&user),
+        target,
+        name,
+        ip,
+        &db,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Reads the size of a record from the output of `psh on-record`, if the record contains video.
+fn record_size(output: &Output) -> Result<Option<(u32, u32)>> {
+    let size = if let Some([Ok(width), Ok(height)]) = std::str::from_utf8(&output.stdout)?
+        .trim()
+        .split("x")
+        .map(|x| x.parse::<u32>())
+        .collect::<Vec<_>>()
+        .get(0..2)
+    {
+        Some((*width, *height))
+    } else {
+        None
+    };
+
+    Ok(size)
+}
+
 /// The route that uploads a record to a capsule for a specific gos.
 #[post("/upload-record/<id>/<gos>", data = "<data>")]
 pub async fn upload_record(
@@ -232,17 +967,7 @@ pub async fn upload_record(
         &format!("{}", uuid),
     ])?;
 
-    let size = if let Some([Ok(width), Ok(height)]) = std::str::from_utf8(&res.stdout)?
-        .trim()
-        .split("x")
-        .map(|x| x.parse::<u32>())
-        .collect::<Vec<_>>()
-        .get(0..2)
-    {
-        Some((*width, *height))
-    } else {
-        None
-    };
+    let size = record_size(&res)?;
 
     gos.record = Some(Record {
         uuid,
@@ -301,6 +1026,81 @@ pub async fn upload_pointer(
     Ok(capsule.to_json(role, &db).await?)
 }
 
+/// The route that uploads a background music to a capsule.
+///
+/// The music is played quietly during the whole capsule until its settings are changed.
+#[post("/upload-audio/<id>?<name>", data = "<data>")]
+pub async fn upload_audio(
+    user: User,
+    db: Db,
+    config: &S<Config>,
+    id: HashId,
+    name: Option<String>,
+    data: Data<'_>,
+) -> Result<Value> {
+    let (mut capsule, role) = user
+        .get_capsule_with_permission(*id, Role::Write, &db)
+        .await?;
+
+    let uuid = Uuid::new_v4();
+    let assets = config.data_path.join(format!("{}", *id)).join("assets");
+    let input = assets.join(format!("{}.upload", uuid));
+    let output = assets.join(format!("{}.m4a", uuid));
+
+    data.open(256_i32.mebibytes()).into_file(&input).await?;
+    let imported = import_audio(&input, &output);
+    remove_file(&input).await.ok();
+    imported?;
+
+    let name = name.unwrap_or_else(|| String::from("audio"));
+    capsule.audio_tracks.0.push(AudioTrack::new(uuid, name));
+    capsule.set_changed();
+    capsule.save(&db).await?;
+
+    Ok(capsule.to_json(role, &db).await?)
+}
+
+/// The route that updates the background musics of a capsule.
+///
+/// The tracks can only use files uploaded to the capsule, and the files that are no longer used
+/// are removed.
+#[post("/audio-tracks/<id>", data = "<data>")]
+pub async fn set_audio_tracks(
+    user: User,
+    db: Db,
+    config: &S<Config>,
+    id: HashId,
+    data: Json<Vec<AudioTrack>>,
+) -> Result<Value> {
+    let (mut capsule, role) = user
+        .get_capsule_with_permission(*id, Role::Write, &db)
+        .await?;
+
+    let tracks = data.0;
+    for track in &tracks {
+        track.check()?;
+
+        if !capsule.audio_tracks.0.iter().any(|x| x.uuid == track.uuid) {
+            return Err(Error(Status::BadRequest));
+        }
+    }
+
+    let assets = config.data_path.join(format!("{}", *id)).join("assets");
+    for old in &capsule.audio_tracks.0 {
+        if !tracks.iter().any(|x| x.uuid == old.uuid) {
+            remove_file(assets.join(format!("{}.m4a", old.uuid)))
+                .await
+                .ok();
+        }
+    }
+
+    capsule.audio_tracks = EJson(tracks);
+    capsule.set_changed();
+    capsule.save(&db).await?;
+
+    Ok(capsule.to_json(role, &db).await?)
+}
+
 /// The route that replaces a slide with another slide or an extra resource.
 #[post("/replace-slide/<id>/<old_uuid>/<page>", data = "<data>")]
 pub async fn replace_slide(
@@ -318,6 +1118,7 @@ pub async fn replace_slide(
     let (mut capsule, role) = user
         .get_capsule_with_permission(*id, Role::Write, &db)
         .await?;
+    let size = capsule.output_settings(&config).size();
 
     // Find the slide to update
     let mut slide_found = None;
@@ -374,7 +1175,7 @@ pub async fn replace_slide(
             &path,
             &format!("{}.png", output),
             &config.pdf_target_density,
-            &config.pdf_target_size,
+            &size,
         ])?;
     } else if *content_type == ContentType::PDF {
         // Not very clean either, but should work too
@@ -384,7 +1185,7 @@ pub async fn replace_slide(
             &format!("{}[{}]", path, page),
             &format!("{}.png", output),
             &config.pdf_target_density,
-            &config.pdf_target_size,
+            &size,
         ])?;
     } else if content_type.media_type().top() == "video" {
         let socks = socks.inner().clone();
@@ -517,6 +1318,7 @@ pub async fn add_slide(
     let (mut capsule, role) = user
         .get_capsule_with_permission(*id, Role::Write, &db)
         .await?;
+    let size = capsule.output_settings(&config).size();
 
     let gos = if gos >= 0 {
         capsule
@@ -561,7 +1363,7 @@ pub async fn add_slide(
             &path,
             &format!("{}.png", output),
             &config.pdf_target_density,
-            &config.pdf_target_size,
+            &size,
         ])?;
 
         false
@@ -574,7 +1376,7 @@ pub async fn add_slide(
             &format!("{}[{}]", path, page),
             &format!("{}.png", output),
             &config.pdf_target_density,
-            &config.pdf_target_size,
+            &size,
         ])?;
 
         false
@@ -593,11 +1395,7 @@ pub async fn add_slide(
         return Err(Error(Status::UnsupportedMediaType));
     };
 
-    gos.slides.push(Slide {
-        uuid: output_uuid,
-        extra: None,
-        prompt: String::new(),
-    });
+    gos.slides.push(Slide::new(output_uuid, String::new()));
 
     capsule.set_changed();
     capsule.save(&db).await?;
@@ -620,6 +1418,7 @@ pub async fn add_gos(
     let (mut capsule, role) = user
         .get_capsule_with_permission(*id, Role::Write, &db)
         .await?;
+    let size = capsule.output_settings(&config).size();
 
     if gos < 0 || gos as usize > capsule.structure.0.len() {
         return Err(Error(Status::BadRequest));
@@ -660,7 +1459,7 @@ pub async fn add_gos(
             &path,
             &format!("{}.png", output),
             &config.pdf_target_density,
-            &config.pdf_target_size,
+            &size,
         ])?;
 
         false
@@ -673,7 +1472,7 @@ pub async fn add_gos(
             &format!("{}[{}]", path, page),
             &format!("{}.png", output),
             &config.pdf_target_density,
-            &config.pdf_target_size,
+            &size,
         ])?;
 
         false
@@ -692,11 +1491,7 @@ pub async fn add_gos(
         return Err(Error(Status::UnsupportedMediaType));
     };
 
-    gos.slides.push(Slide {
-        uuid: output_uuid,
-        extra: None,
-        prompt: String::new(),
-    });
+    gos.slides.push(Slide::new(output_uuid, String::new()));
 
     capsule.set_changed();
     capsule.save(&db).await?;
@@ -704,71 +1499,87 @@ pub async fn add_gos(
     Ok(capsule.to_json(role, &db).await?)
 }
 
-/// The route that triggers the production of a capsule.
-#[post("/produce/<id>")]
-pub async fn produce(
+/// Spawns the production of a whole capsule, notifying a user when it ends.
+///
+/// The capsule is marked as running while it waits for a task slot, and the production process
+/// and its database connection are only taken once it has a slot, so that queued tasks hold
+/// neither. The publication of the capsule is left as is, callers reset it if the new video
+/// replaces the published one. The returned handle resolves to whether the production succeeded.
+pub fn spawn_production(
+    mut capsule: Capsule,
     user: User,
-    id: HashId,
-    socks: &S<WebSockets>,
-    sem: &S<Arc<Semaphore>>,
-    config: &S<Config>,
-    db: Db,
-) -> Result<()> {
-    let (mut capsule, _) = user
-        .get_capsule_with_permission(*id, Role::Write, &db)
-        .await?;
-
-    if capsule.produced == TaskStatus::Running {
-        return Err(Error(Status::Conflict));
-    }
+    config: &Config,
+    pool: Pool,
+    socks: WebSockets,
+    sem: Arc<Semaphore>,
+) -> JoinHandle<bool> {
+    let id = HARSH.encode(capsule.id);
+    let settings = capsule.output_settings(config);
+    let output_path = config
+        .data_path
+        .join(format!("{}", capsule.id))
+        .join("output.mp4");
 
-    let socks = socks.inner().clone();
-    let sem = sem.inner().clone();
+    tokio::spawn(async move {
+        capsule.produced = TaskStatus::Running;
+        capsule.production_pid = None;
 
-    let output_path = config.data_path.join(format!("{}", *id)).join("output.mp4");
+        match Db::from_pool(pool.clone()).await {
+            Ok(db) => {
+                capsule.save(&db).await.ok();
+            }
+            Err(_) => error!("Could not queue the production of capsule {}", capsule.id),
+        }
 
-    tokio::spawn(async move {
-        let child = Command::new("../scripts/psh")
-            .arg("on-produce")
-            .arg(format!("{}", capsule.id))
-            .arg("-1")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn();
+        let permit = sem.acquire().await;
 
-        let succeed = if let Ok(mut child) = child {
-            capsule.produced = TaskStatus::Running;
-            capsule.published = TaskStatus::Idle;
-            capsule.production_pid = child.id().map(|x| x as i32);
-            capsule.save(&db).await.ok();
+        let db = match Db::from_pool(pool).await {
+            Ok(db) => db,
+            Err(_) => {
+                error!("Could not start the production of capsule {}", capsule.id);
+                return false;
+            }
+        };
 
-            if let Some(stdin) = child.stdin.as_mut() {
-                stdin
-                    .write_all(json!(capsule.structure.0).to_string().as_bytes())
-                    .await
-                    .unwrap();
+        let child = if permit.is_ok() {
+            Command::new("../scripts/psh")
+                .arg("on-produce")
+                .arg(format!("{}", capsule.id))
+                .arg("-1")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()
+        } else {
+            None
+        };
 
-                if let Ok(_) = sem.acquire().await {
-                    child.stdin.unwrap();
-                    let stdout = child.stdout.take().unwrap();
-                    let reader = BufReader::new(stdout);
+        let succeed = if let Some(mut child) = child {
+            capsule.production_pid = child.id().map(|x| x as i32);
+            capsule.save(&db).await.ok();
 
-                    let mut lines = reader.lines();
-                    while let Some(line) = lines.next_line().await.unwrap() {
-                        capsule
-                            .notify_production_progress(
-                                &id.hash(),
-                                &format!("{}", line),
-                                &db,
-                                &socks,
-                            )
-                            .await
-                            .ok();
-                    }
-                    true
-                } else {
-                    false
+            if let Some(mut stdin) = child.stdin.take() {
+                let input = format!(
+                    "{}\n{}\n{}\n",
+                    json!(capsule.production_structure()),
+                    json!(settings),
+                    json!(capsule.audio_tracks.0)
+                );
+                stdin.write_all(input.as_bytes()).await.unwrap();
+                drop(stdin);
+
+                let stdout = child.stdout.take().unwrap();
+                let reader = BufReader::new(stdout);
+
+                let mut lines = reader.lines();
+                while let Some(line) = lines.next_line().await.unwrap() {
+                    capsule
+                        .notify_production_progress(&id, &format!("{}", line), &db, &socks)
+                        .await
+                        .ok();
                 }
+
+                true
             } else {
                 false
             }
@@ -777,6 +1588,7 @@ pub async fn produce(
         };
 
         capsule.produced = if succeed {
+            capsule.produced_on = Some(Utc::now().naive_utc());
             TaskStatus::Done
         } else {
             TaskStatus::Idle
@@ -808,10 +1620,7 @@ pub async fn produce(
         capsule.save(&db).await.ok();
 
         if succeed {
-            capsule
-                .notify_production(&id.hash(), &db, &socks)
-                .await
-                .ok();
+            capsule.notify_production(&id, &db, &socks).await.ok();
 
             user.notify(
                 &socks,
@@ -834,7 +1643,43 @@ pub async fn produce(
             .await
             .ok();
         };
-    });
+
+        succeed
+    })
+}
+
+/// The route that triggers the production of a capsule.
+#[post("/produce/<id>")]
+pub async fn produce(
+    user: User,
+    id: HashId,
+    socks: &S<WebSockets>,
+    sem: &S<Arc<Semaphore>>,
+    config: &S<Config>,
+    db: Db,
+    pool: &S<Pool>,
+) -> Result<()> {
+    let (mut capsule, _) = user
+        .get_capsule_with_permission(*id, Role::Write, &db)
+        .await?;
+
+    if capsule.produced == TaskStatus::Running {
+        return Err(Error(Status::Conflict));
+    }
+
+    // The new video has to be published and reviewed again.
+    capsule.published = TaskStatus::Idle;
+    capsule.approval = ApprovalStatus::Unrequested;
+    capsule.approval_comment = None;
+
+    spawn_production(
+        capsule,
+        user,
+        &config,
+        pool.inner().clone(),
+        socks.inner().clone(),
+        sem.inner().clone(),
+    );
 
     Ok(())
 }
@@ -845,6 +1690,7 @@ pub async fn produce_gos(
     user: User,
     id: HashId,
     gos: i32,
+    config: &S<Config>,
     socks: &S<WebSockets>,
     sem: &S<Arc<Semaphore>>,
     db: Db,
@@ -857,6 +1703,8 @@ pub async fn produce_gos(
         return Err(Error(Status::Conflict));
     }
 
+    let settings = capsule.output_settings(&config);
+
     let socks = socks.inner().clone();
     let sem = sem.inner().clone();
 
@@ -872,14 +1720,19 @@ pub async fn produce_gos(
         let succeed = if let Ok(mut child) = child {
             capsule.produced = TaskStatus::Running;
             capsule.published = TaskStatus::Idle;
+            capsule.approval = ApprovalStatus::Unrequested;
+            capsule.approval_comment = None;
             capsule.production_pid = child.id().map(|x| x as i32);
             capsule.save(&db).await.ok();
 
             if let Some(stdin) = child.stdin.as_mut() {
-                stdin
-                    .write_all(json!(capsule.structure.0).to_string().as_bytes())
-                    .await
-                    .unwrap();
+                let input = format!(
+                    "{}\n{}\n{}\n",
+                    json!(capsule.production_structure()),
+                    json!(settings),
+                    json!(capsule.audio_tracks.0)
+                );
+                stdin.write_all(input.as_bytes()).await.unwrap();
 
                 if let Ok(_) = sem.acquire().await {
                     child.stdin.unwrap();
@@ -910,6 +1763,7 @@ pub async fn produce_gos(
         };
 
         capsule.produced = if succeed {
+            capsule.produced_on = Some(Utc::now().naive_utc());
             TaskStatus::Done
         } else {
             TaskStatus::Idle
@@ -975,58 +1829,78 @@ pub async fn cancel_production(user: User, id: HashId, db: Db) -> Result<()> {
     Ok(())
 }
 
-/// The route that publishes a capsule.
-#[post("/publish/<id>")]
-pub async fn publish(
+/// Spawns the publication of a produced capsule, notifying a user when it ends.
+///
+/// Like productions, the publication process and its database connection are only taken once
+/// the capsule has a task slot. The returned handle resolves to whether the publication
+/// succeeded.
+pub fn spawn_publication(
+    mut capsule: Capsule,
     user: User,
-    id: HashId,
-    config: &S<Config>,
-    db: Db,
-    socks: &S<WebSockets>,
-    sem: &S<Arc<Semaphore>>,
-) -> Result<()> {
-    let (mut capsule, _) = user
-        .get_capsule_with_permission(*id, Role::Write, &db)
-        .await?;
+    config: &Config,
+    pool: Pool,
+    socks: WebSockets,
+    sem: Arc<Semaphore>,
+) -> JoinHandle<bool> {
+    let id = HARSH.encode(capsule.id);
+    let input = config
+        .data_path
+        .join(format!("{}", capsule.id))
+        .join("output.mp4");
+    let output = config
+        .data_path
+        .join(format!("{}", capsule.id))
+        .join("output");
 
-    if capsule.produced != TaskStatus::Done || capsule.published != TaskStatus::Idle {
-        return Err(Error(Status::Conflict));
-    }
+    tokio::spawn(async move {
+        capsule.published = TaskStatus::Running;
+        capsule.publication_pid = None;
 
-    let input = config.data_path.join(format!("{}", *id)).join("output.mp4");
-    let output = config.data_path.join(format!("{}", *id)).join("output");
+        match Db::from_pool(pool.clone()).await {
+            Ok(db) => {
+                capsule.save(&db).await.ok();
+            }
+            Err(_) => error!("Could not queue the publication of capsule {}", capsule.id),
+        }
 
-    let socks = socks.inner().clone();
-    let sem = sem.inner().clone();
+        let permit = sem.acquire().await;
+
+        let db = match Db::from_pool(pool).await {
+            Ok(db) => db,
+            Err(_) => {
+                error!("Could not start the publication of capsule {}", capsule.id);
+                return false;
+            }
+        };
 
-    tokio::spawn(async move {
         remove_dir_all(&output).await.ok();
 
-        let child = Command::new("../scripts/psh")
-            .arg("on-publish")
-            .arg(input)
-            .arg(output)
-            .arg(format!("{}", capsule.prompt_subtitles))
-            .stdin(Stdio::piped())
-            .spawn();
+        let child = if permit.is_ok() {
+            Command::new("../scripts/psh")
+                .arg("on-publish")
+                .arg(input)
+                .arg(output)
+                .arg(format!("{}", capsule.prompt_subtitles))
+                .stdin(Stdio::piped())
+                .spawn()
+                .ok()
+        } else {
+            None
+        };
 
-        let succeed = if let Ok(mut child) = child {
-            if let Some(stdin) = child.stdin.as_mut() {
+        let succeed = if let Some(mut child) = child {
+            capsule.publication_pid = child.id().map(|x| x as i32);
+            capsule.save(&db).await.ok();
+
+            if let Some(mut stdin) = child.stdin.take() {
                 stdin
-                    .write_all(json!(capsule.structure.0).to_string().as_bytes())
+                    .write_all(json!(capsule.production_structure()).to_string().as_bytes())
                     .await
                     .unwrap();
+                drop(stdin);
 
-                capsule.published = TaskStatus::Running;
-                capsule.publication_pid = child.id().map(|x| x as i32);
-                capsule.save(&db).await.ok();
-
-                if let Ok(_) = sem.acquire().await {
-                    let res = child.wait().await;
-                    res.map(|x| x.success()).unwrap_or_else(|_| false)
-                } else {
-                    false
-                }
+                let res = child.wait().await;
+                res.map(|x| x.success()).unwrap_or_else(|_| false)
             } else {
                 false
             }
@@ -1035,6 +1909,7 @@ pub async fn publish(
         };
 
         capsule.published = if succeed {
+            capsule.published_on = Some(Utc::now().naive_utc());
             TaskStatus::Done
         } else {
             TaskStatus::Idle
@@ -1044,10 +1919,7 @@ pub async fn publish(
         capsule.save(&db).await.ok();
 
         if succeed {
-            capsule
-                .notify_publication(&id.hash(), &db, &socks)
-                .await
-                .ok();
+            capsule.notify_publication(&id, &db, &socks).await.ok();
 
             user.notify(
                 &socks,
@@ -1073,6 +1945,65 @@ pub async fn publish(
             .await
             .ok();
         }
+
+        succeed
+    })
+}
+
+/// The route that publishes a capsule.
+#[post("/publish/<id>")]
+pub async fn publish(
+    user: User,
+    id: HashId,
+    config: &S<Config>,
+    db: Db,
+    socks: &S<WebSockets>,
+    sem: &S<Arc<Semaphore>>,
+    pool: &S<Pool>,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
+) -> Result<()> {
+    let (capsule, _) = user
+        .get_capsule_with_permission(*id, Role::Write, &db)
+        .await?;
+
+    if capsule.produced != TaskStatus::Done || capsule.published != TaskStatus::Idle {
+        return Err(Error(Status::Conflict));
+    }
+
+    if capsule.requires_approval(&config) && capsule.approval != ApprovalStatus::Approved {
+        return Err(Error(Status::Forbidden));
+    }
+
+    let target = Some(format!("capsule:{}", HARSH.encode(capsule.id)));
+    let actor = user.id;
+    let pool = pool.inner().clone();
+
+    let handle = spawn_publication(
+        capsule,
+        user,
+        &config,
+        pool.clone(),
+        socks.inner().clone(),
+        sem.inner().clone(),
+    );
+
+    // The publication is audited once it has succeeded.
+    tokio::spawn(async move {
+        if !handle.await.unwrap_or(false) {
+            return;
+        }
+
+        let result = async {
+            let db = Db::from_pool(pool).await?;
+            let actor = User::get_by_id(actor, &db).await?;
+            AuditEvent::log(AuditAction::Publish, actor.as_ref(), target, None, ip, &db).await?;
+            Ok::<(), Error>(())
+        };
+
+        if let Err(e) = result.await {
+            error!("Could not audit the publication of a capsule: {:?}", e);
+        }
     });
 
     Ok(())
@@ -1106,7 +2037,14 @@ pub async fn cancel_publication(user: User, id: HashId, db: Db) -> Result<()> {
 
 /// The route that unpublishes a capsule.
 #[post("/unpublish/<id>")]
-pub async fn unpublish(user: User, id: HashId, db: Db, config: &S<Config>) -> Result<()> {
+pub async fn unpublish(
+    user: User,
+    id: HashId,
+    db: Db,
+    config: &S<Config>,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
+) -> Result<()> {
     let (mut capsule, _) = user
         .get_capsule_with_permission(*id, Role::Write, &db)
         .await?;
@@ -1121,6 +2059,16 @@ pub async fn unpublish(user: User, id: HashId, db: Db, config: &S<Config>) -> Re
     let output = config.data_path.join(format!("{}", *id)).join("output");
     remove_dir_all(output).await?;
 
+    AuditEvent::log(
+        AuditAction::Unpublish,
+        Some(&user),
+        Some(format!("capsule:{}", HARSH.encode(capsule.id))),
+        None,
+        ip,
+        &db,
+    )
+    .await?;
+
     Ok(())
 }
 
@@ -1154,24 +2102,49 @@ pub async fn cancel_video_upload(user: User, id: HashId, db: Db) -> Result<()> {
 #[derive(Serialize, Deserialize)]
 pub struct Invite {
     /// The username or email of the invited user.
-    username: String,
+    pub username: String,
 
     /// The role to which the user would be given.
-    role: Role,
+    pub role: Role,
 }
 
 /// The route that invites a user to access a capsule.
+///
+/// If nobody has an account with the specified email address yet, an invitation is sent by
+/// email, and the capsule will be shared with them once they have an account.
 #[post("/invite/<id>", data = "<data>")]
-pub async fn invite(user: User, id: HashId, db: Db, data: Json<Invite>) -> Result<()> {
+pub async fn invite(
+    user: User,
+    id: HashId,
+    db: Db,
+    config: &S<Config>,
+    data: Json<Invite>,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
+) -> Result<()> {
     // user must be the owner of the capsule.
     let (capsule, _) = user
         .get_capsule_with_permission(*id, Role::Owner, &db)
         .await?;
 
     let Invite { username, role } = data.0;
-    let invited = User::get_by_username_or_email(&username, &db)
-        .await?
-        .ok_or(Error(Status::BadRequest))?;
+
+    if role == Role::Owner {
+        return Err(Error(Status::BadRequest));
+    }
+
+    let target = Some(format!("capsule:{}", HARSH.encode(capsule.id)));
+
+    let invited = match User::get_by_username_or_email(&username, &db).await? {
+        Some(invited) => invited,
+        None if username.contains('@') => {
+            let details = Some(format!("{} as {:?}", username, role));
+            PendingInvitation::new(username, role, &capsule, &user, &config, &db).await?;
+            AuditEvent::log(AuditAction::Invite, Some(&user), target, details, ip, &db).await?;
+            return Ok(());
+        }
+        None => return Err(Error(Status::BadRequest)),
+    };
 
     // invited must not already be invited
     if invited
@@ -1184,12 +2157,22 @@ pub async fn invite(user: User, id: HashId, db: Db, data: Json<Invite>) -> Resul
 
     capsule.add_user(&invited, role, &db).await?;
 
+    let details = Some(format!("{} as {:?}", invited.username, role));
+    AuditEvent::log(AuditAction::Invite, Some(&user), target, details, ip, &db).await?;
+
     Ok(())
 }
 
 /// The route that changes the role of a user to access a capsule.
 #[post("/change-role/<id>", data = "<data>")]
-pub async fn change_role(user: User, id: HashId, db: Db, data: Json<Invite>) -> Result<()> {
+pub async fn change_role(
+    user: User,
+    id: HashId,
+    db: Db,
+    data: Json<Invite>,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
+) -> Result<()> {
     // user must be the owner of the capsule.
     let (capsule, _) = user
         .get_capsule_with_permission(*id, Role::Owner, &db)
@@ -1200,8 +2183,39 @@ pub async fn change_role(user: User, id: HashId, db: Db, data: Json<Invite>) ->
         .await?
         .ok_or(Error(Status::BadRequest))?;
 
+    // Ownership can only be given through a transfer.
+    if role == Role::Owner {
+        return Err(Error(Status::BadRequest));
+    }
+
+    // The actual role of the invited user, admins being owners of every capsule otherwise.
+    let current = capsule
+        .users(&db)
+        .await?
+        .into_iter()
+        .find(|(x, _)| x.id == invited.id)
+        .map(|(_, role)| role)
+        .ok_or(Error(Status::BadRequest))?;
+
+    if current == Role::Owner {
+        return Err(Error(Status::BadRequest));
+    }
+
     capsule.update_role(&invited, role, &db).await?;
 
+    AuditEvent::log(
+        AuditAction::ChangeRole,
+        Some(&user),
+        Some(format!("capsule:{}", HARSH.encode(capsule.id))),
+        Some(format!(
+            "{} from {:?} to {:?}",
+            invited.username, current, role
+        )),
+        ip,
+        &db,
+    )
+    .await?;
+
     Ok(())
 }
 
@@ -1209,20 +2223,51 @@ pub async fn change_role(user: User, id: HashId, db: Db, data: Json<Invite>) ->
 #[derive(Serialize, Deserialize)]
 pub struct Deinvite {
     /// The username or email of user to deinvite.
-    username: String,
+    pub username: String,
 }
 
 /// Removes user from a capsule.
 #[post("/deinvite/<id>", data = "<data>")]
-pub async fn deinvite(user: User, id: HashId, db: Db, data: Json<Deinvite>) -> Result<()> {
+pub async fn deinvite(
+    user: User,
+    id: HashId,
+    db: Db,
+    data: Json<Deinvite>,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
+) -> Result<()> {
     let (capsule, _) = user
         .get_capsule_with_permission(*id, Role::Owner, &db)
         .await?;
 
     let Deinvite { username } = data.0;
-    let deinvited = User::get_by_username_or_email(&username, &db)
-        .await?
-        .ok_or(Error(Status::BadRequest))?;
+    let target = Some(format!("capsule:{}", HARSH.encode(capsule.id)));
+
+    let deinvited = match User::get_by_username_or_email(&username, &db).await? {
+        Some(deinvited) => deinvited,
+        None => {
+            // The person may not have an account yet.
+            let invitation = capsule
+                .pending_invitations(&db)
+                .await?
+                .into_iter()
+                .filter(|x| x.email == username)
+                .nth(0)
+                .ok_or(Error(Status::BadRequest))?;
+
+            invitation.delete(&db).await?;
+            AuditEvent::log(
+                AuditAction::Deinvite,
+                Some(&user),
+                target,
+                Some(username),
+                ip,
+                &db,
+            )
+            .await?;
+            return Ok(());
+        }
+    };
 
     // This is a little bit overkill but hey, I've not found better for now...
     let (_, role) = deinvited
@@ -1235,5 +2280,214 @@ pub async fn deinvite(user: User, id: HashId, db: Db, data: Json<Deinvite>) -> R
 
     capsule.remove_user(&deinvited, &db).await?;
 
+    AuditEvent::log(
+        AuditAction::Deinvite,
+        Some(&user),
+        target,
+        Some(deinvited.username.clone()),
+        ip,
+        &db,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The data to transfer the ownership of a capsule.
+#[derive(Serialize, Deserialize)]
+pub struct TransferOwnership {
+    /// The username or email of the user that will become the owner.
+    pub username: String,
+}
+
+/// The route that gives the transfers of capsules waiting for the user to accept them.
+#[get("/incoming-transfers")]
+pub async fn incoming_transfers(user: User, db: Db) -> Result<Value> {
+    let transfers = user.incoming_transfers(&db).await?;
+    let transfers = try_join_all(transfers.iter().map(|x| x.to_json(&db))).await?;
+    Ok(json!(transfers))
+}
+
+/// The route that requests the transfer of the ownership of a capsule to another user.
+///
+/// The capsule is only transferred once the recipient accepts it.
+#[post("/transfer-ownership/<id>", data = "<data>")]
+pub async fn transfer_ownership(
+    user: User,
+    id: HashId,
+    db: Db,
+    socks: &S<WebSockets>,
+    data: Json<TransferOwnership>,
+    _guard: NotImpersonated,
+) -> Result<Value> {
+    let (capsule, _) = user
+        .get_capsule_with_permission(*id, Role::Owner, &db)
+        .await?;
+
+    // Admins get the owner role on every capsule, but only the real owner can give it away.
+    if capsule.owner(&db).await?.id != user.id {
+        return Err(Error(Status::Forbidden));
+    }
+
+    let recipient = User::get_by_username_or_email(&data.0.username, &db)
+        .await?
+        .ok_or(Error(Status::BadRequest))?;
+
+    let transfer = OwnershipTransfer::new(&capsule, &user, &recipient, &db).await?;
+
+    recipient
+        .notify(
+            &socks,
+            "Transfert de capsule",
+            &format!(
+                "{} souhaite vous transférer la capsule \"{}\".",
+                user.username, capsule.name
+            ),
+            &db,
+        )
+        .await?;
+
+    transfer.to_json(&db).await
+}
+
+/// The route that accepts the transfer of a capsule.
+#[post("/accept-transfer/<id>")]
+pub async fn accept_transfer(
+    user: User,
+    id: i32,
+    db: Db,
+    socks: &S<WebSockets>,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
+) -> Result<Value> {
+    let transfer = OwnershipTransfer::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    if transfer.recipient(&db).await?.id != user.id {
+        return Err(Error(Status::NotFound));
+    }
+
+    let sender = transfer.sender(&db).await?;
+    let capsule = transfer.accept(&db).await?;
+
+    AuditEvent::log(
+        AuditAction::AcceptTransfer,
+        Some(&user),
+        Some(format!("capsule:{}", HARSH.encode(capsule.id))),
+        Some(sender.username.clone()),
+        ip,
+        &db,
+    )
+    .await?;
+
+    sender
+        .notify(
+            &socks,
+            "Transfert de capsule",
+            &format!(
+                "{} a accepté le transfert de la capsule \"{}\".",
+                user.username, capsule.name
+            ),
+            &db,
+        )
+        .await?;
+
+    capsule.notify_change(&db, &socks).await?;
+
+    capsule.to_json(Role::Owner, &db).await
+}
+
+/// The route that rejects or cancels the transfer of a capsule.
+///
+/// It can be called by the recipient to reject the transfer, or by the sender to cancel it.
+#[delete("/transfer/<id>")]
+pub async fn cancel_transfer(user: User, id: i32, db: Db, _guard: NotImpersonated) -> Result<()> {
+    let transfer = OwnershipTransfer::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    if transfer.recipient(&db).await?.id != user.id && transfer.sender(&db).await?.id != user.id {
+        return Err(Error(Status::NotFound));
+    }
+
+    transfer.delete(&db).await?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_key_sorts_numbers_by_value() {
+        let mut names = vec!["slide10.png", "slide2.png", "slide1.png"];
+        names.sort_by_key(|x| natural_key(x));
+        assert_eq!(names, vec!["slide1.png", "slide2.png", "slide10.png"]);
+    }
+
+    #[test]
+    fn natural_key_ignores_case() {
+        assert!(natural_key("Slide.png") == natural_key("slide.png"));
+        assert!(natural_key("a2.png") < natural_key("B1.png"));
+    }
+
+    #[test]
+    fn natural_key_handles_directories_and_huge_numbers() {
+        assert!(natural_key("part2/slide1.png") < natural_key("part10/slide1.png"));
+        assert!(natural_key("99999999999999999999999.png") > natural_key("1.png"));
+    }
+
+    #[test]
+    fn parse_markdown_slides_splits_slides_and_notes() {
+        let slides = parse_markdown_slides("# A\n\n---\n\n# B\nNote: first\nsecond\n");
+        assert_eq!(
+            slides,
+            vec![
+                (String::from("# A\n"), String::new()),
+                (String::from("\n# B"), String::from("first\nsecond")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_markdown_slides_skips_front_matter_and_empty_slides() {
+        let slides = parse_markdown_slides("---\ntitle: x\n---\n\n---\n\n# A");
+        assert_eq!(slides, vec![(String::from("\n# A"), String::new())]);
+    }
+
+    #[test]
+    fn parse_markdown_slides_ignores_separators_in_code_and_headings() {
+        let slides = parse_markdown_slides("```\n\n---\n\n```\nTitle\n---\nNote: in code");
+        assert_eq!(slides.len(), 1);
+        assert!(slides[0].0.contains("---"));
+        assert_eq!(slides[0].1, "in code");
+    }
+
+    /// Builds the output of a successful command printing `stdout`.
+    fn output(stdout: &str) -> Output {
+        use std::os::unix::process::ExitStatusExt;
+
+        Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: vec![],
+        }
+    }
+
+    #[test]
+    fn record_size_reads_the_video_size() {
+        assert_eq!(
+            record_size(&output("1280x720\n")).unwrap(),
+            Some((1280, 720))
+        );
+    }
+
+    #[test]
+    fn record_size_is_none_without_video() {
+        assert_eq!(record_size(&output("")).unwrap(), None);
+        assert_eq!(record_size(&output("audio\n")).unwrap(), None);
+        assert_eq!(record_size(&output("1280x\n")).unwrap(), None);
+    }
+}
@@ -0,0 +1,177 @@
+//! This module contains the routes to review capsules with comments.
+
+use serde::{Deserialize, Serialize};
+
+use uuid::Uuid;
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Json, Value};
+use rocket::State as S;
+
+use crate::db::capsule::Role;
+use crate::db::comment::Comment;
+use crate::db::user::User;
+use crate::websockets::WebSockets;
+use crate::{Db, Error, HashId, Result};
+
+/// Retrieves a comment checking that the user has the sufficient role on its capsule.
+async fn get_comment_with_permission(
+    user: &User,
+    id: i32,
+    permission: Role,
+    db: &Db,
+) -> Result<(Comment, Role)> {
+    let comment = Comment::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    let capsule = comment.capsule(&db).await?;
+    let (_, role) = user
+        .get_capsule_with_permission(capsule.id, permission, &db)
+        .await?;
+
+    Ok((comment, role))
+}
+
+/// The route that lists the comments of a capsule.
+#[get("/comments/<id>")]
+pub async fn get_comments(user: User, id: HashId, db: Db) -> Result<Value> {
+    let (capsule, _) = user
+        .get_capsule_with_permission(*id, Role::Read, &db)
+        .await?;
+
+    let mut comments = vec![];
+    for comment in capsule.comments(&db).await? {
+        comments.push(comment.to_json(&db).await?);
+    }
+
+    Ok(json!(comments))
+}
+
+/// The json format to write a comment.
+#[derive(Serialize, Deserialize)]
+pub struct NewComment {
+    /// The content of the comment.
+    pub content: String,
+
+    /// The index of the gos the comment refers to, if any.
+    pub gos: Option<i32>,
+
+    /// The uuid of the slide the comment refers to, if any.
+    pub slide: Option<Uuid>,
+
+    /// The time of the record of the gos the comment refers to, in ms, if any.
+    pub time_ms: Option<i32>,
+}
+
+/// The route that writes a comment on a capsule.
+#[post("/comment/<id>", data = "<data>")]
+pub async fn new_comment(
+    user: User,
+    id: HashId,
+    db: Db,
+    socks: &S<WebSockets>,
+    data: Json<NewComment>,
+) -> Result<Value> {
+    let (capsule, _) = user
+        .get_capsule_with_permission(*id, Role::Comment, &db)
+        .await?;
+
+    let NewComment {
+        content,
+        gos,
+        slide,
+        time_ms,
+    } = data.0;
+
+    if content.trim().is_empty() {
+        return Err(Error(Status::BadRequest));
+    }
+
+    // Check that the gos and slide exist.
+    if let Some(gos) = gos {
+        if gos < 0 || gos as usize >= capsule.structure.0.len() {
+            return Err(Error(Status::BadRequest));
+        }
+    }
+
+    if let Some(slide) = slide {
+        let found = capsule
+            .structure
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| gos.map(|x| x as usize == *i).unwrap_or(true))
+            .any(|(_, x)| x.slides.iter().any(|s| s.uuid == slide));
+
+        if !found {
+            return Err(Error(Status::BadRequest));
+        }
+    }
+
+    if time_ms.map(|x| x < 0).unwrap_or(false) || (time_ms.is_some() && gos.is_none()) {
+        return Err(Error(Status::BadRequest));
+    }
+
+    let comment = Comment::new(
+        content,
+        gos,
+        slide.map(|x| format!("{}", x)),
+        time_ms,
+        &capsule,
+        &user,
+        &db,
+    )
+    .await?;
+
+    comment.notify("comment_added", &user, &db, &socks).await?;
+
+    comment.to_json(&db).await
+}
+
+/// The route that marks a comment as resolved or not.
+///
+/// Only users that can edit the capsule, or the author of the comment, can resolve it.
+#[post("/resolve-comment/<id>/<resolved>")]
+pub async fn resolve_comment(
+    user: User,
+    id: i32,
+    resolved: bool,
+    db: Db,
+    socks: &S<WebSockets>,
+) -> Result<Value> {
+    let (mut comment, role) = get_comment_with_permission(&user, id, Role::Comment, &db).await?;
+
+    if role < Role::Write && comment.author(&db).await?.id != user.id {
+        return Err(Error(Status::Forbidden));
+    }
+
+    comment.resolved = resolved;
+    comment.save(&db).await?;
+
+    comment
+        .notify("comment_resolved", &user, &db, &socks)
+        .await?;
+
+    comment.to_json(&db).await
+}
+
+/// The route that deletes a comment.
+///
+/// Only the owner of the capsule, or the author of the comment, can delete it.
+#[delete("/comment/<id>")]
+pub async fn delete_comment(user: User, id: i32, db: Db, socks: &S<WebSockets>) -> Result<()> {
+    let (comment, role) = get_comment_with_permission(&user, id, Role::Comment, &db).await?;
+
+    if role < Role::Owner && comment.author(&db).await?.id != user.id {
+        return Err(Error(Status::Forbidden));
+    }
+
+    comment
+        .notify("comment_deleted", &user, &db, &socks)
+        .await?;
+
+    comment.delete(&db).await?;
+
+    Ok(())
+}
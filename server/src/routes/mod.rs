@@ -12,13 +12,19 @@ use rocket::State as S;
 
 use crate::config::Config;
 use crate::db::capsule::Role;
+use crate::db::session::Impersonation;
 use crate::db::user::{Plan, User};
 use crate::templates::{index_html, unlogged_html};
 use crate::{Db, Error, HashId, Lang, Result};
 
 pub mod admin;
+pub mod approval;
 pub mod capsule;
+pub mod comment;
 pub mod notification;
+pub mod organization;
+pub mod project;
+pub mod tag;
 pub mod user;
 pub mod watch;
 
@@ -100,7 +106,7 @@ where
 }
 
 /// Prepares the global flags.
-pub fn global_flags(config: &S<Config>, lang: &Lang) -> Value {
+pub fn global_flags(config: &S<Config>, lang: &Lang, impersonation: &Impersonation) -> Value {
     json!({
         "root": config.root,
         "socket_root": config.socket_root,
@@ -110,6 +116,8 @@ pub fn global_flags(config: &S<Config>, lang: &Lang) -> Value {
         "home": config.home,
         "registration_disabled": config.registration_disabled,
         "request_language": lang,
+        "impersonated_by": impersonation.admin.as_ref().map(|x| &x.username),
+        "impersonation_expires": impersonation.expires.map(|x| x.timestamp()),
     })
 }
 
@@ -126,6 +134,7 @@ pub async fn index<'a>(
     db: Db,
     user: Option<User>,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Cors<Either<Html<String>, Redirect>> {
     let (json, redirect) = match user {
         Some(ref user) => (
@@ -143,10 +152,10 @@ pub async fn index<'a>(
     };
 
     let body = match json {
-        Some(Ok(json)) => {
-            index_html(json!({ "user": json, "global": global_flags(&config, &lang) }))
-        }
-        _ => unlogged_html(json!({ "global": global_flags(&config, &lang) })),
+        Some(Ok(json)) => index_html(
+            json!({ "user": json, "global": global_flags(&config, &lang, &impersonation) }),
+        ),
+        _ => unlogged_html(json!({ "global": global_flags(&config, &lang, &impersonation) })),
     };
 
     Cors::new(&config.home, Either::Left(Html(body)))
@@ -158,6 +167,7 @@ pub async fn index_without_cors(
     db: Db,
     user: Option<User>,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
     let (json, redirect) = match user {
         Some(ref user) => (
@@ -175,10 +185,10 @@ pub async fn index_without_cors(
     };
 
     let body = match json {
-        Some(Ok(json)) => {
-            index_html(json!({ "user": json, "global": global_flags(&config, &lang) }))
-        }
-        _ => unlogged_html(json!({ "global": global_flags(&config, &lang) })),
+        Some(Ok(json)) => index_html(
+            json!({ "user": json, "global": global_flags(&config, &lang, &impersonation) }),
+        ),
+        _ => unlogged_html(json!({ "global": global_flags(&config, &lang, &impersonation) })),
     };
 
     Either::Left(Html(body))
@@ -192,8 +202,9 @@ pub async fn preparation(
     user: Option<User>,
     _id: String,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
-    index_without_cors(config, db, user, lang).await
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The route to the acquisition of a capsule.
@@ -205,8 +216,9 @@ pub async fn acquisition(
     _id: String,
     _gos_id: u64,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
-    index_without_cors(config, db, user, lang).await
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The route to the production of a capsule.
@@ -218,8 +230,9 @@ pub async fn production(
     _id: String,
     _gos_id: u64,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
-    index_without_cors(config, db, user, lang).await
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The route to the publication of a capsule.
@@ -230,8 +243,9 @@ pub async fn publication(
     user: Option<User>,
     _id: String,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
-    index_without_cors(config, db, user, lang).await
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The route to the settings page.
@@ -241,8 +255,9 @@ pub async fn settings(
     db: Db,
     user: Option<User>,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
-    index_without_cors(config, db, user, lang).await
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The route to the admin dashboard page.
@@ -252,8 +267,9 @@ pub async fn admin_dashboard(
     db: Db,
     user: Option<User>,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
-    index_without_cors(config, db, user, lang).await
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The route to the admin users page.
@@ -264,8 +280,9 @@ pub async fn admin_users(
     user: Option<User>,
     _page: String,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
-    index_without_cors(config, db, user, lang).await
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The route to the admin user page.
@@ -276,8 +293,9 @@ pub async fn admin_user(
     user: Option<User>,
     _id: String,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
-    index_without_cors(config, db, user, lang).await
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The route to the admin capsules page.
@@ -288,8 +306,9 @@ pub async fn admin_capsules(
     user: Option<User>,
     _page: String,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
-    index_without_cors(config, db, user, lang).await
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The route to the settings of a capsule.
@@ -300,8 +319,9 @@ pub async fn capsule_settings(
     user: Option<User>,
     _id: String,
     lang: Lang,
+    impersonation: Impersonation,
 ) -> Either<Html<String>, Redirect> {
-    index_without_cors(config, db, user, lang).await
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The 404 catcher.
@@ -311,7 +331,8 @@ pub async fn not_found<'a>(request: &'_ Request<'a>) -> Either<Html<String>, Red
     let config = request.guard::<&S<Config>>().await.unwrap();
     let user = Option::<User>::from_request(request).await.unwrap();
     let lang = Lang::from_request(request).await.unwrap();
-    index_without_cors(config, db, user, lang).await
+    let impersonation = Impersonation::from_request(request).await.unwrap();
+    index_without_cors(config, db, user, lang, impersonation).await
 }
 
 /// The route for asset static files that require authorization.
@@ -0,0 +1,227 @@
+//! This module contains the routes to manage organizations.
+
+use serde::{Deserialize, Serialize};
+
+use futures::future::try_join_all;
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Json, Value};
+
+use crate::db::capsule::Role;
+use crate::db::organization::{Organization, OrganizationRole};
+use crate::db::session::NotImpersonated;
+use crate::db::user::{Plan, User};
+use crate::{Db, Error, HashId, Result};
+
+/// Retrieves an organization checking that the user has the sufficient role in it.
+async fn get_organization_with_role(
+    user: &User,
+    id: i32,
+    role: OrganizationRole,
+    db: &Db,
+) -> Result<(Organization, OrganizationRole)> {
+    let organization = Organization::get_by_id(id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    if user.plan == Plan::Admin {
+        return Ok((organization, OrganizationRole::Admin));
+    }
+
+    match organization.role_of(user, db).await? {
+        Some(r) if r >= role => Ok((organization, r)),
+        _ => Err(Error(Status::NotFound)),
+    }
+}
+
+/// The route that creates a new organization.
+#[post("/new-organization/<name>")]
+pub async fn new_organization(user: User, db: Db, name: String) -> Result<Value> {
+    let organization = Organization::new(name, &user, &db).await?;
+    organization.to_json(OrganizationRole::Admin, &db).await
+}
+
+/// The route that gives the organizations of the user.
+#[get("/organizations")]
+pub async fn get_organizations(user: User, db: Db) -> Result<Value> {
+    let organizations = user.organizations(&db).await?;
+    let organizations = organizations
+        .iter()
+        .map(|(organization, role)| organization.to_json(*role, &db));
+    Ok(json!(try_join_all(organizations).await?))
+}
+
+/// The route that gives the organization information.
+#[get("/organization/<id>")]
+pub async fn get_organization(user: User, db: Db, id: i32) -> Result<Value> {
+    let (organization, role) =
+        get_organization_with_role(&user, id, OrganizationRole::Member, &db).await?;
+
+    organization.to_json(role, &db).await
+}
+
+/// The route that deletes an organization.
+///
+/// An organization can only be deleted once it doesn't own any capsule anymore.
+#[delete("/organization/<id>")]
+pub async fn delete_organization(
+    user: User,
+    db: Db,
+    id: i32,
+    _guard: NotImpersonated,
+) -> Result<()> {
+    let (organization, _) =
+        get_organization_with_role(&user, id, OrganizationRole::Admin, &db).await?;
+
+    if !organization.capsules(&db).await?.is_empty() {
+        return Err(Error(Status::Conflict));
+    }
+
+    organization.delete(&db).await?;
+    Ok(())
+}
+
+/// The data to add a member to an organization or change their role.
+#[derive(Serialize, Deserialize)]
+pub struct OrganizationInvite {
+    /// The username or email of the member.
+    username: String,
+
+    /// The role the member will have.
+    role: OrganizationRole,
+}
+
+/// The route that adds a member to an organization.
+#[post("/organization-invite/<id>", data = "<data>")]
+pub async fn invite(
+    user: User,
+    db: Db,
+    id: i32,
+    data: Json<OrganizationInvite>,
+    _guard: NotImpersonated,
+) -> Result<()> {
+    let (organization, _) =
+        get_organization_with_role(&user, id, OrganizationRole::Admin, &db).await?;
+
+    let OrganizationInvite { username, role } = data.0;
+    let invited = User::get_by_username_or_email(&username, &db)
+        .await?
+        .ok_or(Error(Status::BadRequest))?;
+
+    if organization.role_of(&invited, &db).await?.is_some() {
+        return Err(Error(Status::BadRequest));
+    }
+
+    organization.add_member(&invited, role, &db).await?;
+
+    Ok(())
+}
+
+/// The route that changes the role of a member of an organization.
+#[post("/organization-change-role/<id>", data = "<data>")]
+pub async fn change_role(
+    user: User,
+    db: Db,
+    id: i32,
+    data: Json<OrganizationInvite>,
+    _guard: NotImpersonated,
+) -> Result<()> {
+    let (organization, _) =
+        get_organization_with_role(&user, id, OrganizationRole::Admin, &db).await?;
+
+    let OrganizationInvite { username, role } = data.0;
+    let member = User::get_by_username_or_email(&username, &db)
+        .await?
+        .ok_or(Error(Status::BadRequest))?;
+
+    if organization.role_of(&member, &db).await?.is_none() {
+        return Err(Error(Status::BadRequest));
+    }
+
+    organization
+        .update_organization_role(&member, role, &db)
+        .await?;
+
+    Ok(())
+}
+
+/// The data to remove a member from an organization.
+#[derive(Serialize, Deserialize)]
+pub struct OrganizationDeinvite {
+    /// The username or email of the member to remove.
+    username: String,
+}
+
+/// The route that removes a member from an organization.
+///
+/// The organization capsules owned by the member are handed to another admin of the
+/// organization.
+#[post("/organization-deinvite/<id>", data = "<data>")]
+pub async fn deinvite(
+    user: User,
+    db: Db,
+    id: i32,
+    data: Json<OrganizationDeinvite>,
+    _guard: NotImpersonated,
+) -> Result<()> {
+    let (organization, _) =
+        get_organization_with_role(&user, id, OrganizationRole::Admin, &db).await?;
+
+    let member = User::get_by_username_or_email(&data.0.username, &db)
+        .await?
+        .ok_or(Error(Status::BadRequest))?;
+
+    if organization.role_of(&member, &db).await?.is_none() {
+        return Err(Error(Status::BadRequest));
+    }
+
+    organization.reassign_capsules(&member, &db).await?;
+    organization.remove_member(&member, &db).await?;
+
+    Ok(())
+}
+
+/// The route that gives a capsule to an organization.
+///
+/// The user must own the capsule and be a member of the organization.
+#[post("/organization-capsule/<id>/<capsule_id>")]
+pub async fn add_capsule(user: User, db: Db, id: i32, capsule_id: HashId) -> Result<()> {
+    let (organization, _) =
+        get_organization_with_role(&user, id, OrganizationRole::Member, &db).await?;
+
+    let (mut capsule, _) = user
+        .get_capsule_with_permission(*capsule_id, Role::Owner, &db)
+        .await?;
+
+    if capsule.organization.is_some() {
+        return Err(Error(Status::Conflict));
+    }
+
+    capsule.organization = Some(organization.id);
+    capsule.save(&db).await?;
+
+    Ok(())
+}
+
+/// The route that takes a capsule back from an organization.
+#[delete("/organization-capsule/<id>/<capsule_id>")]
+pub async fn remove_capsule(user: User, db: Db, id: i32, capsule_id: HashId) -> Result<()> {
+    let (organization, _) =
+        get_organization_with_role(&user, id, OrganizationRole::Member, &db).await?;
+
+    let (mut capsule, _) = user
+        .get_capsule_with_permission(*capsule_id, Role::Owner, &db)
+        .await?;
+
+    if capsule.organization != Some(organization.id) {
+        return Err(Error(Status::BadRequest));
+    }
+
+    // The capsule must still have a direct owner once it leaves the organization.
+    capsule.owner(&db).await?;
+
+    capsule.organization = None;
+    capsule.save(&db).await?;
+
+    Ok(())
+}
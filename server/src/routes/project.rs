@@ -0,0 +1,156 @@
+//! This module contains the routes to manage the projects.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Json, Value};
+
+use ergol::tokio_postgres::types::Json as EJson;
+
+use crate::db::capsule::Role;
+use crate::db::session::NotImpersonated;
+use crate::db::user::User;
+use crate::routes::capsule::{Deinvite, Invite};
+use crate::{Db, Error, HashId, Result};
+
+/// The route that gives the projects of the user, and the ones shared with them.
+#[get("/projects")]
+pub async fn get_projects(user: User, db: Db) -> Result<Value> {
+    let mut projects = vec![];
+
+    for project in user.projects(&db).await? {
+        projects.push(project.to_json(Role::Owner, &db).await?);
+    }
+
+    for share in user.project_shares(&db).await? {
+        let project = share.project(&db).await?;
+        projects.push(project.to_json(share.role, &db).await?);
+    }
+
+    Ok(json!(projects))
+}
+
+/// The route that gives the project information.
+#[get("/project/<id>")]
+pub async fn get_project(user: User, id: HashId, db: Db) -> Result<Value> {
+    let (project, role) = user
+        .get_project_with_permission(*id, Role::Read, &db)
+        .await?;
+
+    project.to_json(role, &db).await
+}
+
+/// The json format to edit a project.
+#[derive(Serialize, Deserialize)]
+pub struct ProjectEdit {
+    /// The id of the project to edit.
+    pub id: HashId,
+
+    /// The new name of the project.
+    pub name: String,
+
+    /// The new description of the project.
+    pub description: String,
+
+    /// The capsules of the project, in their new order.
+    pub capsules: Vec<HashId>,
+}
+
+/// The route that updates a project.
+#[post("/edit-project", data = "<data>")]
+pub async fn edit_project(user: User, db: Db, data: Json<ProjectEdit>) -> Result<Value> {
+    let ProjectEdit {
+        id,
+        name,
+        description,
+        capsules,
+    } = data.0;
+
+    let (mut project, role) = user
+        .get_project_with_permission(*id, Role::Write, &db)
+        .await?;
+
+    let mut order = capsules.into_iter().map(|x| *x).collect::<Vec<_>>();
+
+    // The new order must be a permutation of the capsules of the project.
+    let current = project.capsules(&db).await?;
+    let old = current.iter().map(|x| x.id).collect::<HashSet<_>>();
+    let new = order.iter().copied().collect::<HashSet<_>>();
+
+    if old != new || order.len() != current.len() {
+        return Err(Error(Status::BadRequest));
+    }
+
+    // The capsules in the trash keep their place at the end.
+    order.extend(
+        project
+            .all_capsules(&db)
+            .await?
+            .into_iter()
+            .filter(|x| x.deleted.is_some())
+            .map(|x| x.id),
+    );
+
+    if project.name != name {
+        project.rename(name, &db).await?;
+    }
+
+    project.description = description;
+    project.capsule_order = EJson(order);
+    project.save(&db).await?;
+
+    project.to_json(role, &db).await
+}
+
+/// The route that shares a project and all its capsules with a user.
+#[post("/invite-project/<id>", data = "<data>")]
+pub async fn invite(
+    user: User,
+    id: HashId,
+    db: Db,
+    data: Json<Invite>,
+    _guard: NotImpersonated,
+) -> Result<()> {
+    // user must be the owner of the project.
+    let (project, _) = user
+        .get_project_with_permission(*id, Role::Owner, &db)
+        .await?;
+
+    let Invite { username, role } = data.0;
+    let invited = User::get_by_username_or_email(&username, &db)
+        .await?
+        .ok_or(Error(Status::BadRequest))?;
+
+    if project.owner(&db).await?.id == invited.id {
+        return Err(Error(Status::BadRequest));
+    }
+
+    project.share(&invited, role, &db).await?;
+
+    Ok(())
+}
+
+/// Removes a user from a project and its capsules.
+#[post("/deinvite-project/<id>", data = "<data>")]
+pub async fn deinvite(
+    user: User,
+    id: HashId,
+    db: Db,
+    data: Json<Deinvite>,
+    _guard: NotImpersonated,
+) -> Result<()> {
+    let (project, _) = user
+        .get_project_with_permission(*id, Role::Owner, &db)
+        .await?;
+
+    let Deinvite { username } = data.0;
+    let deinvited = User::get_by_username_or_email(&username, &db)
+        .await?
+        .ok_or(Error(Status::BadRequest))?;
+
+    project.unshare(&deinvited, &db).await?;
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! This module contains the routes to organize capsules with tags and folders.
+
+use futures::future::try_join_all;
+
+use rocket::http::Status;
+use rocket::serde::json::{json, Value};
+
+use crate::db::capsule::{Capsule, Role};
+use crate::db::folder::Folder;
+use crate::db::tag::Tag;
+use crate::db::user::User;
+use crate::{Db, Error, HashId, Result};
+
+/// The route that gives the tags of the user.
+#[get("/tags")]
+pub async fn get_tags(user: User, db: Db) -> Result<Value> {
+    let tags = user.tags(&db).await?;
+    let tags = try_join_all(tags.iter().map(|x| x.to_json(&db))).await?;
+    Ok(json!(tags))
+}
+
+/// The route that creates a new tag.
+#[post("/new-tag/<name>")]
+pub async fn new_tag(user: User, db: Db, name: String) -> Result<Value> {
+    Tag::new(name, &user, &db).await?.to_json(&db).await
+}
+
+/// The route that deletes a tag.
+#[delete("/tag/<id>")]
+pub async fn delete_tag(user: User, db: Db, id: i32) -> Result<()> {
+    Tag::get_owned(id, &user, &db).await?.delete(&db).await?;
+    Ok(())
+}
+
+/// The route that puts a tag on a capsule.
+#[post("/tag/<id>/<capsule_id>")]
+pub async fn add_tag(user: User, db: Db, id: i32, capsule_id: HashId) -> Result<Value> {
+    let tag = Tag::get_owned(id, &user, &db).await?;
+
+    let (capsule, _) = user
+        .get_capsule_with_permission(*capsule_id, Role::Read, &db)
+        .await?;
+
+    tag.tag_capsule(&capsule, &db).await?;
+    tag.to_json(&db).await
+}
+
+/// The route that removes a tag from a capsule.
+#[delete("/tag/<id>/<capsule_id>")]
+pub async fn remove_tag(user: User, db: Db, id: i32, capsule_id: HashId) -> Result<Value> {
+    let tag = Tag::get_owned(id, &user, &db).await?;
+
+    let capsule = Capsule::get_by_id(*capsule_id, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    tag.remove_capsule(&capsule, &db).await?;
+    tag.to_json(&db).await
+}
+
+/// The route that gives the folders of the user.
+#[get("/folders")]
+pub async fn get_folders(user: User, db: Db) -> Result<Value> {
+    let folders = user.folders(&db).await?;
+    let folders = try_join_all(folders.iter().map(|x| x.to_json(&db))).await?;
+    Ok(json!(folders))
+}
+
+/// The route that creates a new folder, at the root or inside another folder.
+#[post("/new-folder/<name>?<parent>")]
+pub async fn new_folder(user: User, db: Db, name: String, parent: Option<i32>) -> Result<Value> {
+    Folder::new(name, parent, &user, &db)
+        .await?
+        .to_json(&db)
+        .await
+}
+
+/// The route that renames a folder.
+#[post("/rename-folder/<id>/<name>")]
+pub async fn rename_folder(user: User, db: Db, id: i32, name: String) -> Result<Value> {
+    let mut folder = Folder::get_owned(id, &user, &db).await?;
+
+    if name.is_empty() {
+        return Err(Error(Status::BadRequest));
+    }
+
+    folder.name = name;
+    folder.save(&db).await?;
+    folder.to_json(&db).await
+}
+
+/// The route that moves a folder inside another folder, or at the root.
+#[post("/move-folder/<id>?<parent>")]
+pub async fn move_folder(user: User, db: Db, id: i32, parent: Option<i32>) -> Result<Value> {
+    let mut folder = Folder::get_owned(id, &user, &db).await?;
+    folder.move_to(parent, &db).await?;
+    folder.to_json(&db).await
+}
+
+/// The route that deletes a folder.
+///
+/// The subfolders and capsules of the folder are moved to its parent.
+#[delete("/folder/<id>")]
+pub async fn delete_folder(user: User, db: Db, id: i32) -> Result<()> {
+    Folder::get_owned(id, &user, &db).await?.remove(&db).await
+}
+
+/// The route that moves a capsule in a folder, or at the root.
+#[post("/move-to-folder/<capsule_id>?<folder>")]
+pub async fn move_to_folder(
+    user: User,
+    db: Db,
+    capsule_id: HashId,
+    folder: Option<i32>,
+) -> Result<()> {
+    let (capsule, _) = user
+        .get_capsule_with_permission(*capsule_id, Role::Read, &db)
+        .await?;
+
+    Folder::move_capsule(&capsule, folder, &user, &db).await
+}
@@ -1,14 +1,16 @@
 //! This module contains all the routes that deal with the user.
 
 use std::borrow::Cow;
+use std::net::IpAddr;
+
+use chrono::Utc;
 
 use time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-use tokio::fs::remove_dir_all;
-
 use rocket::form::Form;
+use rocket::fs::NamedFile;
 use rocket::http::{Cookie, CookieJar, SameSite, Status};
 use rocket::response::content::RawHtml as Html;
 use rocket::response::Redirect;
@@ -16,16 +18,21 @@ use rocket::serde::json::{json, Json, Value};
 use rocket::State as S;
 
 use crate::config::Config;
+use crate::db::audit::{AuditAction, AuditEvent};
 use crate::db::capsule::Role;
-use crate::db::session::Session;
+use crate::db::export::DataExport;
+use crate::db::invitation::PendingInvitation;
+use crate::db::session::{Impersonation, NotImpersonated, Session};
+use crate::db::task_status::TaskStatus;
 use crate::db::user::User;
 use crate::routes::global_flags;
 use crate::routes::Cors;
 use crate::templates::unlogged_html;
+use crate::websockets::WebSockets;
 use crate::{Db, Error, Lang, Result};
 
 /// Creates then authentication cookies.
-fn add_cookies(value: &str, config: &Config, cookies: &CookieJar) {
+pub fn add_cookies(value: &str, config: &Config, cookies: &CookieJar) {
     let max_age = Duration::weeks(4);
 
     let v = Cow::into_owned(value.into());
@@ -47,7 +54,7 @@ fn add_cookies(value: &str, config: &Config, cookies: &CookieJar) {
 }
 
 /// Removes the authentication cookies
-fn remove_cookies(value: &str, config: &Config, cookies: &CookieJar) {
+pub fn remove_cookies(value: &str, config: &Config, cookies: &CookieJar) {
     let max_age = Duration::weeks(4);
 
     let v = Cow::into_owned(value.into());
@@ -111,6 +118,32 @@ pub async fn new_user<'a>(db: Db, config: &S<Config>, user: Json<NewUserForm>) -
     Cors::new(&config.home, status)
 }
 
+/// The route of the link sent to a person invited to a capsule who has no account yet.
+///
+/// It shows the signup page with the invitation, so that the account can be created with the
+/// invited email address.
+#[get("/capsule-invitation/<key>")]
+pub async fn capsule_invitation(
+    db: Db,
+    config: &S<Config>,
+    key: String,
+    lang: Lang,
+) -> Result<Html<String>> {
+    let invitation = PendingInvitation::get_valid_by_key(key, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    let body = unlogged_html(json!({
+        "global": global_flags(&config, &lang, &Impersonation::none()),
+        "invitation": {
+            "key": invitation.key,
+            "email": invitation.email,
+        },
+    }));
+
+    Ok(Html(body))
+}
+
 /// The route to active a user.
 #[get("/activate/<key>")]
 pub async fn activate<'a>(
@@ -125,12 +158,15 @@ pub async fn activate<'a>(
         .ok_or(Error(Status::NotFound))?;
 
     user.activated = true;
+    user.activated_on = Some(Utc::now().naive_utc());
     user.activation_key = None;
     user.save(&db).await?;
+    PendingInvitation::attach_to(&user, &db).await?;
     let session = user.save_session(&db).await?;
     add_cookies(&session.secret, &config, cookies);
 
-    let body = unlogged_html(json!({ "global": global_flags(&config, &lang) }));
+    let body =
+        unlogged_html(json!({ "global": global_flags(&config, &lang, &Impersonation::none()) }));
     Ok(Html(body))
 }
 
@@ -157,6 +193,7 @@ pub async fn login_external<'a>(
     cookies: &CookieJar<'_>,
     config: &S<Config>,
     login: Form<LoginForm>,
+    ip: Option<IpAddr>,
 ) -> Cors<Result<Redirect>> {
     let user = match User::get_by_username(&login.username, &db).await {
         Ok(u) => u,
@@ -185,6 +222,13 @@ pub async fn login_external<'a>(
         Err(_) => return Cors::err(&config.home, Status::InternalServerError),
     };
 
+    if AuditEvent::log(AuditAction::Login, Some(&user), None, None, ip, &db)
+        .await
+        .is_err()
+    {
+        return Cors::err(&config.home, Status::InternalServerError);
+    }
+
     add_cookies(&session.secret, &config, cookies);
 
     Cors::ok(&config.home, Redirect::to(config.root.clone()))
@@ -197,6 +241,7 @@ pub async fn login(
     config: &S<Config>,
     cookies: &CookieJar<'_>,
     login: Json<LoginForm>,
+    ip: Option<IpAddr>,
 ) -> Result<Value> {
     let user = User::get_by_username(&login.username, &db)
         .await?
@@ -210,6 +255,8 @@ pub async fn login(
 
     let session = user.save_session(&db).await?;
 
+    AuditEvent::log(AuditAction::Login, Some(&user), None, None, ip, &db).await?;
+
     add_cookies(&session.secret, &config, cookies);
 
     Ok(user.to_json(&db).await?)
@@ -217,7 +264,12 @@ pub async fn login(
 
 /// The logout page.
 #[post("/logout")]
-pub async fn logout(db: Db, config: &S<Config>, cookies: &CookieJar<'_>) -> Result<()> {
+pub async fn logout(
+    db: Db,
+    config: &S<Config>,
+    cookies: &CookieJar<'_>,
+    ip: Option<IpAddr>,
+) -> Result<()> {
     {
         let cookie = cookies.get_private("EXAUTH");
         if let Some(cookie) = cookie {
@@ -225,6 +277,14 @@ pub async fn logout(db: Db, config: &S<Config>, cookies: &CookieJar<'_>) -> Resu
                 .await?
                 .ok_or(Error(Status::NotFound))?;
 
+            // An admin leaving an impersonation session is the one logging out.
+            let user = match session.impersonator {
+                Some(id) => User::get_by_id(id, &db).await?,
+                None => Some(session.owner(&db).await?),
+            };
+
+            AuditEvent::log(AuditAction::Logout, user.as_ref(), None, None, ip, &db).await?;
+
             session.delete(&db).await?;
         }
     }
@@ -287,6 +347,8 @@ pub async fn change_password(
     form: Json<ChangePasswordForm>,
     config: &S<Config>,
     cookies: &CookieJar<'_>,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
 ) -> Result<()> {
     let mut user = match (&form.username_and_old_password, &form.key) {
         (None, None) => return Err(Error(Status::BadRequest)),
@@ -304,6 +366,22 @@ pub async fn change_password(
     add_cookies(&session.secret, &config, cookies);
     user.save(&db).await?;
 
+    let details = if form.key.is_some() {
+        "reset by email"
+    } else {
+        "changed with old password"
+    };
+
+    AuditEvent::log(
+        AuditAction::ChangePassword,
+        Some(&user),
+        Some(format!("user:{}", user.username)),
+        Some(String::from(details)),
+        ip,
+        &db,
+    )
+    .await?;
+
     Ok(())
 }
 
@@ -323,7 +401,8 @@ pub async fn reset_password<'a>(
         _ => return Cors::err(&config.home, Status::InternalServerError),
     };
 
-    let body = unlogged_html(json!({ "global": global_flags(&config, &lang) }));
+    let body =
+        unlogged_html(json!({ "global": global_flags(&config, &lang, &Impersonation::none()) }));
     Cors::ok(&config.home, Html(body))
 }
 
@@ -341,9 +420,27 @@ pub async fn request_change_email(
     db: Db,
     config: &S<Config>,
     form: Json<ChangeEmailForm>,
+    ip: Option<IpAddr>,
+    _guard: NotImpersonated,
 ) -> Result<()> {
+    let old_email = user.email.clone();
+
     user.request_change_email(form.0.new_email, &config.mailer, &db)
         .await?;
+
+    // Without a mailer, the email address is changed right away.
+    if config.mailer.is_none() {
+        AuditEvent::log(
+            AuditAction::ChangeEmail,
+            Some(&user),
+            Some(format!("user:{}", user.username)),
+            Some(format!("{} to {}", old_email, user.email)),
+            ip,
+            &db,
+        )
+        .await?;
+    }
+
     Ok(())
 }
 
@@ -354,10 +451,30 @@ pub async fn validate_email<'a>(
     config: &S<Config>,
     db: Db,
     lang: Lang,
+    ip: Option<IpAddr>,
 ) -> Cors<Result<Html<String>>> {
-    match User::validate_change_email(key, &db).await {
-        Ok(_) => {
-            let body = unlogged_html(json!({ "global": global_flags(&config, &lang) }));
+    let result: Result<()> = async {
+        let (user, old_email) = User::validate_change_email(key, &db).await?;
+
+        AuditEvent::log(
+            AuditAction::ChangeEmail,
+            Some(&user),
+            Some(format!("user:{}", user.username)),
+            Some(format!("{} to {}", old_email, user.email)),
+            ip,
+            &db,
+        )
+        .await?;
+
+        Ok(())
+    }
+    .await;
+
+    match result {
+        Ok(()) => {
+            let body = unlogged_html(
+                json!({ "global": global_flags(&config, &lang, &Impersonation::none()) }),
+            );
             Cors::ok(&config.home, Html(body))
         }
         Err(Error(s)) => Cors::err(&config.home, s),
@@ -378,15 +495,34 @@ pub async fn delete(
     config: &S<Config>,
     form: Json<DeleteUserForm>,
     cookies: &CookieJar<'_>,
+    _guard: NotImpersonated,
 ) -> Result<()> {
     user.test_password(&form.current_password)?;
+
+    let organizations = user.organizations(&db).await?;
+
+    // The organization capsules could not be handed to anyone, another admin must be named or the
+    // organization deleted first.
+    for (organization, _) in &organizations {
+        if organization.is_last_admin(&user, &db).await? {
+            return Err(Error(Status::Conflict));
+        }
+    }
+
+    for export in user.data_exports(&db).await? {
+        export.remove(&config, &db).await?;
+    }
+
+    // Organization capsules stay with the organization.
+    for (organization, _) in organizations {
+        organization.reassign_capsules(&user, &db).await?;
+    }
+
     let capsules = user.capsules(&db).await?;
 
     for (capsule, role) in capsules {
         if role == Role::Owner {
-            let dir = config.data_path.join(format!("{}", capsule.id));
-            remove_dir_all(dir).await?;
-            capsule.delete(&db).await?;
+            capsule.purge(&config, &db).await?;
         }
     }
 
@@ -396,6 +532,135 @@ pub async fn delete(
     Ok(())
 }
 
+/// Route to request an export of all the data of the user.
+///
+/// The archive is built in the background and the user is notified when it is ready.
+#[post("/request-export")]
+pub async fn request_export(
+    user: User,
+    db: Db,
+    config: &S<Config>,
+    socks: &S<WebSockets>,
+    _guard: NotImpersonated,
+) -> Result<Value> {
+    let mut export = DataExport::new(&user, &config, &db).await?;
+    let json = export.to_json();
+
+    let config = config.inner().clone();
+    let socks = socks.inner().clone();
+
+    tokio::spawn(async move {
+        let succeed = export.build(&config, &db).await.is_ok();
+
+        export.status = if succeed {
+            TaskStatus::Done
+        } else {
+            TaskStatus::Failed
+        };
+        export.save(&db).await.ok();
+
+        if succeed {
+            user.notify(
+                &socks,
+                "Export terminé",
+                &format!(
+                    "L'export de vos données est disponible jusqu'au {} à l'adresse {}/api/export/{}.",
+                    export.expires.format("%d/%m/%Y %H:%M"),
+                    config.root,
+                    export.key
+                ),
+                &db,
+            )
+            .await
+            .ok();
+        } else {
+            user.notify(
+                &socks,
+                "Export échoué",
+                "L'export de vos données a échoué.",
+                &db,
+            )
+            .await
+            .ok();
+        }
+    });
+
+    Ok(json)
+}
+
+/// Route to download an export of the data of the user.
+#[get("/export/<key>")]
+pub async fn get_export(
+    user: User,
+    db: Db,
+    config: &S<Config>,
+    key: String,
+    _guard: NotImpersonated,
+) -> Result<NamedFile> {
+    let export = DataExport::get_by_key(key, &db)
+        .await?
+        .ok_or(Error(Status::NotFound))?;
+
+    if export.owner(&db).await?.id != user.id {
+        return Err(Error(Status::NotFound));
+    }
+
+    if export.is_expired() {
+        export.remove(&config, &db).await?;
+        return Err(Error(Status::Gone));
+    }
+
+    if export.status != TaskStatus::Done {
+        return Err(Error(Status::Conflict));
+    }
+
+    NamedFile::open(export.path(&config))
+        .await
+        .map_err(|_| Error(Status::NotFound))
+}
+
+/// Route that ends an impersonation session, logging the admin back in as themselves.
+#[post("/stop-impersonation")]
+pub async fn stop_impersonation(
+    db: Db,
+    config: &S<Config>,
+    cookies: &CookieJar<'_>,
+    ip: Option<IpAddr>,
+) -> Result<Value> {
+    let cookie = cookies
+        .get_private("EXAUTH")
+        .ok_or(Error(Status::Unauthorized))?;
+
+    let session = Session::get_by_secret(cookie.value(), &db)
+        .await?
+        .ok_or(Error(Status::Unauthorized))?;
+
+    let admin = match session.impersonator {
+        Some(id) => User::get_by_id(id, &db).await?,
+        None => return Err(Error(Status::BadRequest)),
+    };
+
+    let user = session.owner(&db).await?;
+    session.delete(&db).await?;
+
+    let admin = admin.ok_or(Error(Status::Unauthorized))?;
+
+    AuditEvent::log(
+        AuditAction::AdminStopImpersonation,
+        Some(&admin),
+        Some(format!("user:{}", user.username)),
+        None,
+        ip,
+        &db,
+    )
+    .await?;
+
+    let session = admin.save_session(&db).await?;
+    add_cookies(&session.secret, &config, cookies);
+
+    admin.to_json(&db).await
+}
+
 /// Unsubsribes the user from the newsletter.
 #[get("/unsubscribe/<key>")]
 pub async fn unsubscribe<'a>(db: Db, config: &S<Config>, key: String) -> Cors<Result<Redirect>> {
@@ -430,7 +695,8 @@ pub async fn validate_invitation<'a>(
     let session = user.save_session(&db).await?;
     add_cookies(&session.secret, &config, cookies);
 
-    let body = unlogged_html(json!({ "global": global_flags(&config, &lang) }));
+    let body =
+        unlogged_html(json!({ "global": global_flags(&config, &lang, &Impersonation::none()) }));
 
     Ok(Html(body))
 }
@@ -459,11 +725,14 @@ pub async fn request_invitation<'a>(
         .ok_or(Error(Status::NotFound))?;
 
     user.activated = true;
+    user.activated_on = Some(Utc::now().naive_utc());
     user.activation_key = None;
     user.save(&db).await?;
+    PendingInvitation::attach_to(&user, &db).await?;
     let session = user.save_session(&db).await?;
     add_cookies(&session.secret, &config, cookies);
 
-    let body = unlogged_html(json!({ "global": global_flags(&config, &lang) }));
+    let body =
+        unlogged_html(json!({ "global": global_flags(&config, &lang, &Impersonation::none()) }));
     Ok(Html(body))
 }
@@ -42,7 +42,8 @@ pub async fn watch<'a>(
         .await?
         .ok_or(Error(Status::NotFound))?;
 
-    if capsule.published != TaskStatus::Done {
+    // Capsules in the trash are not watchable, even if they are still published.
+    if capsule.published != TaskStatus::Done || capsule.deleted.is_some() {
         return Err(Error(Status::NotFound));
     }
 
@@ -108,7 +109,8 @@ pub async fn watch_asset_aux(
         .await?
         .ok_or(Error(Status::NotFound))?;
 
-    if capsule.published != TaskStatus::Done {
+    // Capsules in the trash are not watchable, even if they are still published.
+    if capsule.published != TaskStatus::Done || capsule.deleted.is_some() {
         return Err(Error(Status::NotFound));
     }
 
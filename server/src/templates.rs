@@ -2,6 +2,24 @@
 
 use rocket::serde::json::Value;
 
+/// Escapes the characters of a text that have a meaning in HTML.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
 /// This function formats a validation email with HTML format from an activation url.
 pub fn validation_email_html(activaion_url: &str) -> String {
     format!(
@@ -49,6 +67,30 @@ pub fn validation_invitation_plain_text(activation_url: &str) -> String {
     )
 }
 
+/// This function formats an invitation to collaborate on a capsule with HTML format from a signup url.
+///
+/// The names are chosen by users, they are escaped.
+pub fn capsule_invitation_html(inviter: &str, capsule_name: &str, signup_url: &str) -> String {
+    format!(
+        "<h1>Welcome</h1><p>{} invited you to collaborate on the capsule \"{}\" on Polymny Studio.</p><p><a href=\"{}\">Click here to create your account</a></p>",
+        escape_html(inviter),
+        escape_html(capsule_name),
+        escape_html(signup_url)
+    )
+}
+
+/// This function formats an invitation to collaborate on a capsule with plain text format from a signup url.
+pub fn capsule_invitation_plain_text(
+    inviter: &str,
+    capsule_name: &str,
+    signup_url: &str,
+) -> String {
+    format!(
+        "Welcome!\n\n{} invited you to collaborate on the capsule \"{}\" on Polymny Studio.\n\nTo create your account, please go to the following link:\n{}",
+        inviter, capsule_name, signup_url
+    )
+}
+
 /// This function formats a reset password email with HTML format from a reset url.
 pub fn reset_password_email_html(url: &str) -> String {
     format!(
@@ -314,3 +356,22 @@ pub fn video_html(url: &str) -> String {
         url
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_html_escapes_markup() {
+        assert_eq!(
+            escape_html("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn escape_html_keeps_plain_text() {
+        assert_eq!(escape_html("Déjà vu 42"), "Déjà vu 42");
+        assert_eq!(escape_html(""), "");
+    }
+}